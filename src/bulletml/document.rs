@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use xml::reader::{EventReader, XmlEvent};
+use bulletml::expression::Expression;
+
+pub type NodeId = usize;
+
+const UNRESOLVED: NodeId = ::std::usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectionType {
+    Aim,
+    Absolute,
+    Relative,
+    Sequence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Absolute,
+    Relative,
+    Sequence,
+}
+
+#[derive(Debug, Clone)]
+pub struct Direction {
+    pub kind: DirectionType,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub kind: ValueType,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Action(Vec<NodeId>),
+    ActionRef(NodeId, Vec<Expression>),
+    Bullet {
+        direction: Option<Direction>,
+        speed: Option<Value>,
+        actions: Vec<NodeId>,
+    },
+    BulletRef(NodeId, Vec<Expression>),
+    Fire {
+        direction: Option<Direction>,
+        speed: Option<Value>,
+        bullet: NodeId,
+    },
+    FireRef(NodeId, Vec<Expression>),
+    Repeat { times: Expression, action: NodeId },
+    ChangeDirection {
+        direction: Direction,
+        term: Expression,
+    },
+    ChangeSpeed { speed: Value, term: Expression },
+    Accel {
+        horizontal: Option<Value>,
+        vertical: Option<Value>,
+        term: Expression,
+    },
+    Wait(Expression),
+    Vanish,
+}
+
+#[derive(Debug)]
+pub struct Document {
+    nodes: Vec<Node>,
+    top_actions: Vec<NodeId>,
+}
+
+impl Document {
+    pub fn parse(xml: &str) -> Result<Document, String> {
+        let root = parse_element_tree(xml)?;
+        if root.name != "bulletml" {
+            return Err(format!("Unexpected root element <{}>", root.name));
+        }
+
+        let mut builder = Builder {
+            nodes: vec![],
+            labels: HashMap::new(),
+            references: vec![],
+        };
+        let mut top_actions = vec![];
+        for child in &root.children {
+            let id = builder.build(child)?;
+            if child.name == "action" &&
+               child.attribute("label").map_or(false, |label| label.starts_with("top")) {
+                top_actions.push(id);
+            }
+        }
+        builder.resolve_references()?;
+
+        Ok(Document {
+               nodes: builder.nodes,
+               top_actions: top_actions,
+           })
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn top_actions(&self) -> &[NodeId] {
+        &self.top_actions
+    }
+}
+
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&&(ref n, _)| n == name)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn expression(&self) -> Result<Expression, String> {
+        Expression::parse(&self.text)
+    }
+}
+
+fn parse_element_tree(xml: &str) -> Result<Element, String> {
+    let mut stack: Vec<Element> = vec![];
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|e| format!("Can't parse XML: {}", e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(Element {
+                               name: name.local_name,
+                               attributes: attributes
+                                   .into_iter()
+                                   .map(|a| (a.name.local_name, a.value))
+                                   .collect(),
+                               children: vec![],
+                               text: String::new(),
+                           });
+            }
+            XmlEvent::Characters(text) |
+            XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().expect("Unbalanced XML elements");
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("Can't find root element".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum LabelKind {
+    Action,
+    Bullet,
+    Fire,
+}
+
+struct Builder {
+    nodes: Vec<Node>,
+    labels: HashMap<(LabelKind, String), NodeId>,
+    references: Vec<(NodeId, LabelKind, String)>,
+}
+
+impl Builder {
+    fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn build(&mut self, element: &Element) -> Result<NodeId, String> {
+        let (node, label_kind) = match element.name.as_str() {
+            "action" => {
+                let mut children = vec![];
+                for child in &element.children {
+                    children.push(self.build(child)?);
+                }
+                (Node::Action(children), Some(LabelKind::Action))
+            }
+            "bullet" => {
+                let mut actions = vec![];
+                for child in element
+                        .children
+                        .iter()
+                        .filter(|c| c.name == "action" || c.name == "actionRef") {
+                    actions.push(self.build(child)?);
+                }
+                (Node::Bullet {
+                     direction: build_direction(element.child("direction"))?,
+                     speed: build_value(element.child("speed"))?,
+                     actions: actions,
+                 },
+                 Some(LabelKind::Bullet))
+            }
+            "fire" => {
+                let bullet = element
+                    .children
+                    .iter()
+                    .find(|c| c.name == "bullet" || c.name == "bulletRef")
+                    .ok_or_else(|| "Can't find <fire>'s bullet".to_string())?;
+                (Node::Fire {
+                     direction: build_direction(element.child("direction"))?,
+                     speed: build_value(element.child("speed"))?,
+                     bullet: self.build(bullet)?,
+                 },
+                 Some(LabelKind::Fire))
+            }
+            "actionRef" | "bulletRef" | "fireRef" => {
+                let kind = match element.name.as_str() {
+                    "actionRef" => LabelKind::Action,
+                    "bulletRef" => LabelKind::Bullet,
+                    _ => LabelKind::Fire,
+                };
+                let label = element
+                    .attribute("label")
+                    .ok_or_else(|| format!("Can't find <{}>'s label", element.name))?;
+                let mut params = vec![];
+                for param in element.children.iter().filter(|c| c.name == "param") {
+                    params.push(param.expression()?);
+                }
+                let node = match kind {
+                    LabelKind::Action => Node::ActionRef(UNRESOLVED, params),
+                    LabelKind::Bullet => Node::BulletRef(UNRESOLVED, params),
+                    LabelKind::Fire => Node::FireRef(UNRESOLVED, params),
+                };
+                let id = self.push(node);
+                self.references.push((id, kind, label.to_string()));
+                return Ok(id);
+            }
+            "repeat" => {
+                let action = element
+                    .children
+                    .iter()
+                    .find(|c| c.name == "action" || c.name == "actionRef")
+                    .ok_or_else(|| "Can't find <repeat>'s action".to_string())?;
+                (Node::Repeat {
+                     times: required_child(element, "times")?.expression()?,
+                     action: self.build(action)?,
+                 },
+                 None)
+            }
+            "changeDirection" => {
+                (Node::ChangeDirection {
+                     direction: build_direction(Some(required_child(element, "direction")?))?
+                         .expect("Direction should be present"),
+                     term: required_child(element, "term")?.expression()?,
+                 },
+                 None)
+            }
+            "changeSpeed" => {
+                (Node::ChangeSpeed {
+                     speed: build_value(Some(required_child(element, "speed")?))?
+                         .expect("Speed should be present"),
+                     term: required_child(element, "term")?.expression()?,
+                 },
+                 None)
+            }
+            "accel" => {
+                (Node::Accel {
+                     horizontal: build_value(element.child("horizontal"))?,
+                     vertical: build_value(element.child("vertical"))?,
+                     term: required_child(element, "term")?.expression()?,
+                 },
+                 None)
+            }
+            "wait" => (Node::Wait(element.expression()?), None),
+            "vanish" => (Node::Vanish, None),
+            name => return Err(format!("Unexpected element <{}>", name)),
+        };
+
+        let id = self.push(node);
+        if let (Some(kind), Some(label)) = (label_kind, element.attribute("label")) {
+            self.labels.insert((kind, label.to_string()), id);
+        }
+        Ok(id)
+    }
+
+    fn resolve_references(&mut self) -> Result<(), String> {
+        for &(id, kind, ref label) in &self.references {
+            let target = *self.labels
+                              .get(&(kind, label.clone()))
+                              .ok_or_else(|| format!("Can't find label '{}'", label))?;
+            match self.nodes[id] {
+                Node::ActionRef(ref mut t, _) |
+                Node::BulletRef(ref mut t, _) |
+                Node::FireRef(ref mut t, _) => *t = target,
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn required_child<'a>(element: &'a Element, name: &str) -> Result<&'a Element, String> {
+    element
+        .child(name)
+        .ok_or_else(|| format!("Can't find <{}>'s <{}>", element.name, name))
+}
+
+fn build_direction(element: Option<&Element>) -> Result<Option<Direction>, String> {
+    let element = match element {
+        Some(element) => element,
+        None => return Ok(None),
+    };
+    let kind = match element.attribute("type").unwrap_or("aim") {
+        "aim" => DirectionType::Aim,
+        "absolute" => DirectionType::Absolute,
+        "relative" => DirectionType::Relative,
+        "sequence" => DirectionType::Sequence,
+        kind => return Err(format!("Unknown direction type '{}'", kind)),
+    };
+    Ok(Some(Direction {
+                kind: kind,
+                value: element.expression()?,
+            }))
+}
+
+fn build_value(element: Option<&Element>) -> Result<Option<Value>, String> {
+    let element = match element {
+        Some(element) => element,
+        None => return Ok(None),
+    };
+    let kind = match element.attribute("type").unwrap_or("absolute") {
+        "absolute" => ValueType::Absolute,
+        "relative" => ValueType::Relative,
+        "sequence" => ValueType::Sequence,
+        kind => return Err(format!("Unknown value type '{}'", kind)),
+    };
+    Ok(Some(Value {
+                kind: kind,
+                value: element.expression()?,
+            }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &'static str = r#"<?xml version="1.0" ?>
+<bulletml type="vertical">
+  <action label="top">
+    <repeat>
+      <times>3</times>
+      <action>
+        <fireRef label="aimed"><param>2</param></fireRef>
+        <wait>10</wait>
+      </action>
+    </repeat>
+    <vanish/>
+  </action>
+  <fire label="aimed">
+    <direction type="aim">0</direction>
+    <speed>$1</speed>
+    <bullet/>
+  </fire>
+</bulletml>"#;
+
+    #[test]
+    fn parses_document() {
+        let document = Document::parse(DOCUMENT).expect("Can't parse document");
+        assert_eq!(document.top_actions().len(), 1);
+        let children = match *document.node(document.top_actions()[0]) {
+            Node::Action(ref children) => children.clone(),
+            ref node => panic!("Unexpected top node {:?}", node),
+        };
+        assert_eq!(children.len(), 2);
+        let action = match *document.node(children[0]) {
+            Node::Repeat { action, .. } => action,
+            ref node => panic!("Unexpected node {:?}", node),
+        };
+        match *document.node(children[1]) {
+            Node::Vanish => {}
+            ref node => panic!("Unexpected node {:?}", node),
+        }
+        let fire_ref = match *document.node(action) {
+            Node::Action(ref children) => children[0],
+            ref node => panic!("Unexpected node {:?}", node),
+        };
+        let fire = match *document.node(fire_ref) {
+            Node::FireRef(fire, ref params) => {
+                assert_eq!(params.len(), 1);
+                fire
+            }
+            ref node => panic!("Unexpected node {:?}", node),
+        };
+        match *document.node(fire) {
+            Node::Fire { direction: Some(ref direction), speed: Some(ref speed), .. } => {
+                assert_eq!(direction.kind, DirectionType::Aim);
+                assert_eq!(speed.kind, ValueType::Absolute);
+            }
+            ref node => panic!("Unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(Document::parse("<bulletml>").is_err());
+        assert!(Document::parse("<action label=\"top\"/>").is_err());
+        assert!(Document::parse("<bulletml><unknown/></bulletml>").is_err());
+        assert!(Document::parse("<bulletml><action><actionRef label=\"missing\"/></action>\
+                                 </bulletml>")
+                        .is_err());
+        assert!(Document::parse("<bulletml><action><wait>1 +</wait></action></bulletml>")
+                    .is_err());
+        assert!(Document::parse("<bulletml><fire><bullet/><direction type=\"up\">0\
+                                 </direction></fire></bulletml>")
+                        .is_err());
+    }
+}
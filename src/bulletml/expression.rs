@@ -0,0 +1,246 @@
+use std::str::FromStr;
+use util::Rng;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Number(f32),
+    Rand,
+    Rank,
+    Param(usize),
+    Neg(Box<Expression>),
+    BinOp(Operator, Box<Expression>, Box<Expression>),
+}
+
+pub struct Environment<'a> {
+    pub params: &'a [f32],
+    pub rank: f32,
+    pub rng: &'a mut Rng,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let expression = parser.sum()?;
+        if parser.pos != tokens.len() {
+            return Err(format!("Unexpected token in expression '{}'", source));
+        }
+        Ok(expression)
+    }
+
+    pub fn evaluate(&self, env: &mut Environment) -> f32 {
+        match *self {
+            Expression::Number(n) => n,
+            Expression::Rand => env.rng.next_f32(),
+            Expression::Rank => env.rank,
+            Expression::Param(i) => env.params.get(i).cloned().unwrap_or(0.0),
+            Expression::Neg(ref e) => -e.evaluate(env),
+            Expression::BinOp(op, ref a, ref b) => {
+                let a = a.evaluate(env);
+                let b = b.evaluate(env);
+                match op {
+                    Operator::Add => a + b,
+                    Operator::Sub => a - b,
+                    Operator::Mul => a * b,
+                    Operator::Div => if b == 0.0 { 0.0 } else { a / b },
+                    Operator::Rem => if b == 0.0 { 0.0 } else { a % b },
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Variable(String),
+    Operator(char),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_digit(10) || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_digit(10) || chars[i] == '.') {
+                i += 1;
+            }
+            let number = chars[start..i].iter().cloned().collect::<String>();
+            tokens.push(Token::Number(f32::from_str(&number)
+                                          .map_err(|_| {
+                                                       format!("Can't parse number '{}'", number)
+                                                   })?));
+        } else if c == '$' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i].is_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Variable(chars[start..i].iter().cloned().collect()));
+        } else if "+-*/%".contains(c) {
+            tokens.push(Token::Operator(c));
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            i += 1;
+        } else {
+            return Err(format!("Unexpected character '{}' in expression '{}'", c, source));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek_operator(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(&Token::Operator(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut result = self.product()?;
+        while let Some(c) = self.peek_operator() {
+            let op = match c {
+                '+' => Operator::Add,
+                '-' => Operator::Sub,
+                _ => break,
+            };
+            self.pos += 1;
+            result = Expression::BinOp(op, Box::new(result), Box::new(self.product()?));
+        }
+        Ok(result)
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut result = self.unary()?;
+        while let Some(c) = self.peek_operator() {
+            let op = match c {
+                '*' => Operator::Mul,
+                '/' => Operator::Div,
+                '%' => Operator::Rem,
+                _ => break,
+            };
+            self.pos += 1;
+            result = Expression::BinOp(op, Box::new(result), Box::new(self.unary()?));
+        }
+        Ok(result)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.peek_operator() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expression::Neg(Box::new(self.unary()?)))
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        let token = self.tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "Unexpected end of expression".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expression::Number(n)),
+            Token::Variable(ref name) if name == "rand" => Ok(Expression::Rand),
+            Token::Variable(ref name) if name == "rank" => Ok(Expression::Rank),
+            Token::Variable(ref name) => {
+                match usize::from_str(name) {
+                    Ok(i) if i > 0 => Ok(Expression::Param(i - 1)),
+                    _ => Err(format!("Unknown variable '${}'", name)),
+                }
+            }
+            Token::Open => {
+                let result = self.sum()?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return Err("Unbalanced parentheses in expression".to_string());
+                }
+                self.pos += 1;
+                Ok(result)
+            }
+            _ => Err("Unexpected token in expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::Rng;
+
+    fn evaluate(source: &str, params: &[f32]) -> f32 {
+        let mut rng = Rng::new(1);
+        let mut env = Environment {
+            params: params,
+            rank: 0.5,
+            rng: &mut rng,
+        };
+        Expression::parse(source).expect("Can't parse expression").evaluate(&mut env)
+    }
+
+    #[test]
+    fn parses_numbers_and_precedence() {
+        assert_eq!(evaluate("42", &[]), 42.0);
+        assert_eq!(evaluate("1.5 + 2 * 3", &[]), 7.5);
+        assert_eq!(evaluate("(1 + 2) * 3", &[]), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3", &[]), 3.0);
+        assert_eq!(evaluate("7 % 4", &[]), 3.0);
+        assert_eq!(evaluate("-2 * -3", &[]), 6.0);
+        assert_eq!(evaluate("5 / 0", &[]), 0.0);
+    }
+
+    #[test]
+    fn parses_variables() {
+        assert_eq!(evaluate("$1 + $2 * 10", &[1.0, 2.0]), 21.0);
+        assert_eq!(evaluate("$3", &[1.0]), 0.0);
+        assert_eq!(evaluate("$rank * 2", &[]), 1.0);
+        let rand = evaluate("$rand", &[]);
+        assert!(rand >= 0.0 && rand < 1.0);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!(Expression::parse("").is_err());
+        assert!(Expression::parse("1 +").is_err());
+        assert!(Expression::parse("(1 + 2").is_err());
+        assert!(Expression::parse("1 2").is_err());
+        assert!(Expression::parse("1.2.3").is_err());
+        assert!(Expression::parse("$0").is_err());
+        assert!(Expression::parse("$foo").is_err());
+        assert!(Expression::parse("2 ^ 3").is_err());
+    }
+}
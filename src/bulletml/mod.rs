@@ -0,0 +1,54 @@
+mod document;
+mod expression;
+mod runner;
+
+use std::collections::HashMap;
+
+pub use self::document::Document;
+pub use self::runner::{Runner, Context};
+
+const DOCUMENTS: &'static [(&'static str, &'static str)] =
+    &[("spiral", include_str!("../../data/bulletml/spiral.xml")),
+      ("burst", include_str!("../../data/bulletml/burst.xml")),
+      ("curtain", include_str!("../../data/bulletml/curtain.xml")),
+      ("blossom", include_str!("../../data/bulletml/blossom.xml"))];
+
+#[derive(Debug)]
+pub struct Library {
+    documents: Vec<Document>,
+    names: HashMap<String, usize>,
+}
+
+impl Library {
+    pub fn new() -> Library {
+        let mut library = Library {
+            documents: vec![],
+            names: HashMap::new(),
+        };
+        for &(name, xml) in DOCUMENTS {
+            library
+                .add(name, xml)
+                .unwrap_or_else(|e| panic!("Can't load BulletML document {}: {}", name, e));
+        }
+        library
+    }
+
+    pub fn add(&mut self, name: &str, xml: &str) -> Result<usize, String> {
+        let document = Document::parse(xml)?;
+        self.documents.push(document);
+        self.names.insert(name.to_string(), self.documents.len() - 1);
+        Ok(self.documents.len() - 1)
+    }
+
+    pub fn document_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).cloned()
+    }
+
+    pub fn document(&self, index: usize) -> &Document {
+        &self.documents[index]
+    }
+
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+}
@@ -0,0 +1,547 @@
+use std::mem;
+use scene::SceneObject;
+use util::{Angle, FPoint, Rng};
+use bulletml::Library;
+use bulletml::document::{Document, Node, NodeId, DirectionType, ValueType, Direction, Value};
+use bulletml::expression::{Expression, Environment};
+use snapshot::{Reader, Writer};
+
+const FRAMES_PER_SECOND: f32 = 60.0;
+const SPEED_UNIT: f32 = 0.3; //Screens/s per pixel/frame on a 200 pixels wide screen
+const DEFAULT_SPEED: f32 = 1.0;
+const MAX_STEPS_PER_FRAME: u32 = 10_000;
+
+pub struct Context<'a> {
+    pub target: FPoint,
+    pub rank: f32,
+    pub rng: &'a mut Rng,
+}
+
+#[derive(Debug)]
+pub struct Fired {
+    pub pos: FPoint,
+    pub direction: Angle,
+    pub speed: f32,
+    pub runner: Option<Runner>,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    action: NodeId,
+    pc: usize,
+    repeat: u32,
+    params: Vec<f32>,
+}
+
+#[derive(Debug, Clone)]
+struct Thread {
+    stack: Vec<Frame>,
+    wait: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    frames: u32,
+    delta: f32,
+}
+
+impl Change {
+    fn none() -> Change {
+        Change {
+            frames: 0,
+            delta: 0.0,
+        }
+    }
+
+    fn next(&mut self) -> Option<f32> {
+        if self.frames == 0 {
+            None
+        } else {
+            self.frames -= 1;
+            Some(self.delta)
+        }
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.write_u32(self.frames);
+        writer.write_f32(self.delta);
+    }
+
+    fn read(reader: &mut Reader) -> Result<Change, String> {
+        Ok(Change {
+               frames: reader.read_u32()?,
+               delta: reader.read_f32()?,
+           })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Runner {
+    document: usize,
+    threads: Vec<Thread>,
+    frame_time: f32,
+    direction_change: Change,
+    speed_change: Change,
+    horizontal_change: Change,
+    vertical_change: Change,
+    drift: FPoint,
+    last_direction: f32,
+    last_speed: f32,
+}
+
+impl Runner {
+    pub fn new(library: &Library, document: usize) -> Runner {
+        let actions = library
+            .document(document)
+            .top_actions()
+            .iter()
+            .map(|&action| (action, vec![]))
+            .collect();
+        Runner::with_actions(document, actions)
+    }
+
+    fn with_actions(document: usize, actions: Vec<(NodeId, Vec<f32>)>) -> Runner {
+        Runner {
+            document: document,
+            threads: actions
+                .into_iter()
+                .map(|(action, params)| {
+                    Thread {
+                        stack: vec![Frame {
+                                        action: action,
+                                        pc: 0,
+                                        repeat: 1,
+                                        params: params,
+                                    }],
+                        wait: 0.0,
+                    }
+                })
+                .collect(),
+            frame_time: 1.0,
+            direction_change: Change::none(),
+            speed_change: Change::none(),
+            horizontal_change: Change::none(),
+            vertical_change: Change::none(),
+            drift: FPoint::new(0.0, 0.0),
+            last_direction: 0.0,
+            last_speed: DEFAULT_SPEED,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.threads.is_empty() && self.direction_change.frames == 0 &&
+        self.speed_change.frames == 0 && self.horizontal_change.frames == 0 &&
+        self.vertical_change.frames == 0 && self.drift.x() == 0.0 &&
+        self.drift.y() == 0.0
+    }
+
+    pub fn write(&self, writer: &mut Writer) {
+        writer.write_usize(self.document);
+        writer.write_usize(self.threads.len());
+        for thread in &self.threads {
+            writer.write_usize(thread.stack.len());
+            for frame in &thread.stack {
+                writer.write_usize(frame.action);
+                writer.write_usize(frame.pc);
+                writer.write_u32(frame.repeat);
+                writer.write_usize(frame.params.len());
+                for &param in &frame.params {
+                    writer.write_f32(param);
+                }
+            }
+            writer.write_f32(thread.wait);
+        }
+        writer.write_f32(self.frame_time);
+        self.direction_change.write(writer);
+        self.speed_change.write(writer);
+        self.horizontal_change.write(writer);
+        self.vertical_change.write(writer);
+        writer.write_point(self.drift);
+        writer.write_f32(self.last_direction);
+        writer.write_f32(self.last_speed);
+    }
+
+    pub fn read(reader: &mut Reader, library: &Library) -> Result<Runner, String> {
+        let document = reader.read_index(library.documents().len(), "BulletML document")?;
+        let nodes = library.document(document).nodes();
+        let mut threads = vec![];
+        for _ in 0..reader.read_usize()? {
+            let mut stack = vec![];
+            for _ in 0..reader.read_usize()? {
+                let action = reader.read_index(nodes.len(), "BulletML action")?;
+                let pc = reader.read_usize()?;
+                match nodes[action] {
+                    Node::Action(ref children) if pc <= children.len() => {}
+                    _ => return Err(format!("Invalid BulletML action {} at {}", action, pc)),
+                }
+                let repeat = reader.read_u32()?;
+                let mut params = vec![];
+                for _ in 0..reader.read_usize()? {
+                    params.push(reader.read_f32()?);
+                }
+                stack.push(Frame {
+                               action: action,
+                               pc: pc,
+                               repeat: repeat,
+                               params: params,
+                           });
+            }
+            threads.push(Thread {
+                             stack: stack,
+                             wait: reader.read_f32()?,
+                         });
+        }
+        Ok(Runner {
+               document: document,
+               threads: threads,
+               frame_time: reader.read_f32()?,
+               direction_change: Change::read(reader)?,
+               speed_change: Change::read(reader)?,
+               horizontal_change: Change::read(reader)?,
+               vertical_change: Change::read(reader)?,
+               drift: reader.read_point()?,
+               last_direction: reader.read_f32()?,
+               last_speed: reader.read_f32()?,
+           })
+    }
+
+    pub fn update(&mut self,
+                  library: &Library,
+                  object: &mut SceneObject,
+                  duration_s: f32,
+                  context: &mut Context,
+                  fired: &mut Vec<Fired>) {
+        let document = library.document(self.document);
+        self.frame_time += duration_s * FRAMES_PER_SECOND;
+        while self.frame_time >= 1.0 && !object.to_delete {
+            self.frame_time -= 1.0;
+            self.step(document, object, context, fired);
+        }
+    }
+
+    fn step(&mut self,
+            document: &Document,
+            object: &mut SceneObject,
+            context: &mut Context,
+            fired: &mut Vec<Fired>) {
+        if let Some(delta) = self.direction_change.next() {
+            object.direction_angle = object.direction_angle.add_deg(delta);
+        }
+        if let Some(delta) = self.speed_change.next() {
+            object.speed += delta * SPEED_UNIT;
+        }
+        if let Some(delta) = self.horizontal_change.next() {
+            *self.drift.mut_x() += delta;
+        }
+        if let Some(delta) = self.vertical_change.next() {
+            *self.drift.mut_y() += delta;
+        }
+        *object.pos.mut_x() += self.drift.x() * SPEED_UNIT / FRAMES_PER_SECOND;
+        *object.pos.mut_y() += self.drift.y() * SPEED_UNIT / FRAMES_PER_SECOND;
+
+        let mut threads = mem::replace(&mut self.threads, vec![]);
+        for thread in &mut threads {
+            self.run_thread(thread, document, object, context, fired);
+        }
+        threads.retain(|thread| !thread.stack.is_empty());
+        self.threads = threads;
+    }
+
+    fn run_thread(&mut self,
+                  thread: &mut Thread,
+                  document: &Document,
+                  object: &mut SceneObject,
+                  context: &mut Context,
+                  fired: &mut Vec<Fired>) {
+        if thread.wait > 0.0 {
+            thread.wait -= 1.0;
+            if thread.wait > 0.0 {
+                return;
+            }
+        }
+
+        for _ in 0..MAX_STEPS_PER_FRAME {
+            let next = match thread.stack.last_mut() {
+                Some(frame) => next_child(document, frame),
+                None => return,
+            };
+            let id = match next {
+                Some(id) => id,
+                None => {
+                    thread.stack.pop();
+                    continue;
+                }
+            };
+
+            let params = thread
+                .stack
+                .last()
+                .map(|frame| frame.params.clone())
+                .unwrap_or_default();
+            match *document.node(id) {
+                Node::Action(..) |
+                Node::ActionRef(..) => {
+                    let (action, params) = resolve(document, id, &params, context);
+                    thread.stack.push(Frame {
+                                          action: action,
+                                          pc: 0,
+                                          repeat: 1,
+                                          params: params,
+                                      });
+                }
+                Node::Repeat {
+                    ref times,
+                    action,
+                } => {
+                    let times = evaluate(times, &params, context);
+                    if times >= 1.0 {
+                        let (action, params) = resolve(document, action, &params, context);
+                        thread.stack.push(Frame {
+                                              action: action,
+                                              pc: 0,
+                                              repeat: times as u32,
+                                              params: params,
+                                          });
+                    }
+                }
+                Node::Fire { .. } |
+                Node::FireRef(..) => {
+                    self.fire(document, id, &params, object, context, fired);
+                }
+                Node::ChangeDirection {
+                    ref direction,
+                    ref term,
+                } => {
+                    let term = evaluate(term, &params, context).max(1.0);
+                    let current = bulletml_direction(object.direction_angle);
+                    let value = evaluate(&direction.value, &params, context);
+                    let delta = match direction.kind {
+                        DirectionType::Sequence => value,
+                        DirectionType::Aim => {
+                            normalize_deg(aim_direction(object.pos, context.target) + value -
+                                          current) / term
+                        }
+                        DirectionType::Absolute => normalize_deg(value - current) / term,
+                        DirectionType::Relative => normalize_deg(value) / term,
+                    };
+                    self.direction_change = Change {
+                        frames: term as u32,
+                        delta: delta,
+                    };
+                }
+                Node::ChangeSpeed {
+                    ref speed,
+                    ref term,
+                } => {
+                    let term = evaluate(term, &params, context).max(1.0);
+                    let current = object.speed / SPEED_UNIT;
+                    self.speed_change = Change {
+                        frames: term as u32,
+                        delta: change_delta(speed, current, term, &params, context),
+                    };
+                }
+                Node::Accel {
+                    ref horizontal,
+                    ref vertical,
+                    ref term,
+                } => {
+                    let term = evaluate(term, &params, context).max(1.0);
+                    if let Some(ref horizontal) = *horizontal {
+                        let current = self.drift.x();
+                        self.horizontal_change = Change {
+                            frames: term as u32,
+                            delta: change_delta(horizontal, current, term, &params, context),
+                        };
+                    }
+                    if let Some(ref vertical) = *vertical {
+                        let current = self.drift.y();
+                        self.vertical_change = Change {
+                            frames: term as u32,
+                            delta: change_delta(vertical, current, term, &params, context),
+                        };
+                    }
+                }
+                Node::Wait(ref frames) => {
+                    thread.wait = evaluate(frames, &params, context);
+                    if thread.wait > 0.0 {
+                        return;
+                    }
+                }
+                Node::Vanish => {
+                    object.to_delete = true;
+                    thread.stack.clear();
+                    return;
+                }
+                Node::Bullet { .. } |
+                Node::BulletRef(..) => {}
+            }
+        }
+
+        thread.stack.clear();
+    }
+
+    fn fire(&mut self,
+            document: &Document,
+            id: NodeId,
+            params: &[f32],
+            object: &SceneObject,
+            context: &mut Context,
+            fired: &mut Vec<Fired>) {
+        let (fire, fire_params) = resolve(document, id, params, context);
+        let (fire_direction, fire_speed, bullet) = match *document.node(fire) {
+            Node::Fire {
+                ref direction,
+                ref speed,
+                bullet,
+            } => (direction, speed, bullet),
+            _ => return,
+        };
+        let (bullet, bullet_params) = resolve(document, bullet, &fire_params, context);
+        let (bullet_direction, bullet_speed, actions) = match *document.node(bullet) {
+            Node::Bullet {
+                ref direction,
+                ref speed,
+                ref actions,
+            } => (direction, speed, actions),
+            _ => return,
+        };
+
+        let direction = match (fire_direction.as_ref(), bullet_direction.as_ref()) {
+            (Some(direction), _) => self.direction(direction, &fire_params, object, context),
+            (None, Some(direction)) => {
+                self.direction(direction, &bullet_params, object, context)
+            }
+            (None, None) => aim_direction(object.pos, context.target),
+        };
+        let speed = match (fire_speed.as_ref(), bullet_speed.as_ref()) {
+            (Some(speed), _) => self.speed(speed, &fire_params, object, context),
+            (None, Some(speed)) => self.speed(speed, &bullet_params, object, context),
+            (None, None) => DEFAULT_SPEED,
+        };
+        self.last_direction = direction;
+        self.last_speed = speed;
+
+        let runner = if actions.is_empty() {
+            None
+        } else {
+            let actions = actions
+                .iter()
+                .map(|&action| resolve(document, action, &bullet_params, context))
+                .collect();
+            Some(Runner::with_actions(self.document, actions))
+        };
+
+        fired.push(Fired {
+                       pos: object.pos,
+                       direction: Angle::from_deg(direction),
+                       speed: speed * SPEED_UNIT,
+                       runner: runner,
+                   });
+    }
+
+    fn direction(&self,
+                 direction: &Direction,
+                 params: &[f32],
+                 object: &SceneObject,
+                 context: &mut Context)
+                 -> f32 {
+        let value = evaluate(&direction.value, params, context);
+        match direction.kind {
+            DirectionType::Aim => aim_direction(object.pos, context.target) + value,
+            DirectionType::Absolute => value,
+            DirectionType::Relative => bulletml_direction(object.direction_angle) + value,
+            DirectionType::Sequence => self.last_direction + value,
+        }
+    }
+
+    fn speed(&self,
+             speed: &Value,
+             params: &[f32],
+             object: &SceneObject,
+             context: &mut Context)
+             -> f32 {
+        let value = evaluate(&speed.value, params, context);
+        match speed.kind {
+            ValueType::Absolute => value,
+            ValueType::Relative => object.speed / SPEED_UNIT + value,
+            ValueType::Sequence => self.last_speed + value,
+        }
+    }
+}
+
+fn evaluate(expression: &Expression, params: &[f32], context: &mut Context) -> f32 {
+    expression.evaluate(&mut Environment {
+                             params: params,
+                             rank: context.rank,
+                             rng: &mut *context.rng,
+                         })
+}
+
+fn next_child(document: &Document, frame: &mut Frame) -> Option<NodeId> {
+    let children = match *document.node(frame.action) {
+        Node::Action(ref children) => children,
+        _ => return None,
+    };
+    if frame.pc >= children.len() && frame.repeat > 1 && !children.is_empty() {
+        frame.repeat -= 1;
+        frame.pc = 0;
+    }
+    children
+        .get(frame.pc)
+        .map(|&id| {
+                 frame.pc += 1;
+                 id
+             })
+}
+
+fn resolve(document: &Document,
+           id: NodeId,
+           params: &[f32],
+           context: &mut Context)
+           -> (NodeId, Vec<f32>) {
+    match *document.node(id) {
+        Node::ActionRef(target, ref args) |
+        Node::BulletRef(target, ref args) |
+        Node::FireRef(target, ref args) => {
+            (target,
+             args.iter()
+                 .map(|arg| evaluate(arg, params, context))
+                 .collect())
+        }
+        _ => (id, params.to_vec()),
+    }
+}
+
+fn change_delta(value: &Value,
+                current: f32,
+                term: f32,
+                params: &[f32],
+                context: &mut Context)
+                -> f32 {
+    let target = evaluate(&value.value, params, context);
+    match value.kind {
+        ValueType::Absolute => (target - current) / term,
+        ValueType::Relative => target / term,
+        ValueType::Sequence => target,
+    }
+}
+
+fn bulletml_direction(direction_angle: Angle) -> f32 {
+    direction_angle.as_deg() + 90.0
+}
+
+fn aim_direction(from: FPoint, to: FPoint) -> f32 {
+    let delta = to - from;
+    delta.y().atan2(delta.x()).to_degrees() + 90.0
+}
+
+fn normalize_deg(deg: f32) -> f32 {
+    let deg = deg % 360.0;
+    if deg > 180.0 {
+        deg - 360.0
+    } else if deg < -180.0 {
+        deg + 360.0
+    } else {
+        deg
+    }
+}
@@ -100,6 +100,55 @@ impl CollisionDetector {
         where I: IntoIterator<Item = &'a mut SceneObject>,
               J: IntoIterator<Item = &'b mut SceneObject>,
               F: Fn(&mut SceneObject, &mut SceneObject)
+    {
+        self.for_each_pair(objects_a, objects_b, 0.0, |a, b| {
+            if !self.range_collision(a, b, 0.0) {
+                return;
+            }
+
+            if !self.hitbox_collision(a, b, 0.0) {
+                return;
+            }
+
+            if !self.convex_collision(a, b) {
+                return;
+            }
+
+            on_collision(a, b);
+        });
+    }
+
+    pub fn detect_near_misses<'a, 'b, I, J, F>(&self,
+                                               objects_a: I,
+                                               objects_b: J,
+                                               distance: f32,
+                                               on_near_miss: F)
+        where I: IntoIterator<Item = &'a mut SceneObject>,
+              J: IntoIterator<Item = &'b mut SceneObject>,
+              F: Fn(&mut SceneObject, &mut SceneObject)
+    {
+        self.for_each_pair(objects_a, objects_b, distance, |a, b| {
+            if !self.range_collision(a, b, distance) {
+                return;
+            }
+
+            if !self.hitbox_collision(a, b, distance) {
+                return;
+            }
+
+            let separation = self.convex_separation(a, b);
+            if separation <= 0.0 || separation > distance {
+                return;
+            }
+
+            on_near_miss(a, b);
+        });
+    }
+
+    fn for_each_pair<'a, 'b, I, J, F>(&self, objects_a: I, objects_b: J, margin: f32, f: F)
+        where I: IntoIterator<Item = &'a mut SceneObject>,
+              J: IntoIterator<Item = &'b mut SceneObject>,
+              F: Fn(&mut SceneObject, &mut SceneObject)
     {
         let mut segments: Vec<Vec<usize>> = Vec::with_capacity(SEGMENTS_COUNT as usize);
 
@@ -110,50 +159,41 @@ impl CollisionDetector {
         let mut bs: Vec<_> = objects_b.into_iter().collect();
 
         for (i, b) in bs.iter().enumerate() {
-            for segment in SegmentsIterator::new(b) {
+            for segment in SegmentsIterator::new(b, margin) {
                 segments[segment as usize].push(i);
             }
         }
 
         for a in objects_a {
-            for segment in SegmentsIterator::new(a) {
+            for segment in SegmentsIterator::new(a, 0.0) {
                 for i in &segments[segment as usize] {
-                    let b = &mut bs[*i];
-                    if !self.range_collision(a, b) {
-                        continue;
-                    }
-
-                    if !self.hitbox_collision(a, b) {
-                        continue;
-                    }
-
-                    if !self.convex_collision(a, b) {
-                        continue;
-                    }
-
-                    on_collision(a, b);
+                    f(a, &mut bs[*i]);
                 }
             }
         }
     }
 
-    fn range_collision(&self, a: &SceneObject, b: &SceneObject) -> bool {
+    fn range_collision(&self, a: &SceneObject, b: &SceneObject, margin: f32) -> bool {
         let distance = a.pos - b.pos;
-        distance.x().abs() < (a.collision_data().range.x() + b.collision_data().range.x()) &&
-        distance.y().abs() < (a.collision_data().range.y() + b.collision_data().range.y())
+        let range = a.collision_data().range + b.collision_data().range;
+        distance.x().abs() < range.x() + margin && distance.y().abs() < range.y() + margin
     }
 
-    fn hitbox_collision(&self, a: &SceneObject, b: &SceneObject) -> bool {
+    fn hitbox_collision(&self, a: &SceneObject, b: &SceneObject, margin: f32) -> bool {
         let a_hitbox = a.collision_data().hitbox();
         let b_hitbox = b.collision_data().hitbox();
-        a.pos.x() + a_hitbox.right > b.pos.x() - b_hitbox.left &&
-        b.pos.x() + b_hitbox.right > a.pos.x() - a_hitbox.left &&
-        a.pos.y() + a_hitbox.bottom > b.pos.y() - b_hitbox.top &&
-        b.pos.y() + b_hitbox.bottom > a.pos.y() - a_hitbox.top
+        a.pos.x() + a_hitbox.right + margin > b.pos.x() - b_hitbox.left &&
+        b.pos.x() + b_hitbox.right + margin > a.pos.x() - a_hitbox.left &&
+        a.pos.y() + a_hitbox.bottom + margin > b.pos.y() - b_hitbox.top &&
+        b.pos.y() + b_hitbox.bottom + margin > a.pos.y() - a_hitbox.top
     }
 
     fn convex_collision(&self, a: &SceneObject, b: &SceneObject) -> bool {
+        self.convex_separation(a, b) <= 0.0
+    }
 
+    fn convex_separation(&self, a: &SceneObject, b: &SceneObject) -> f32 {
+        let mut separation = ::std::f32::MIN;
         for angle in (&a.collision_data().normals)
                 .into_iter()
                 .chain(&b.collision_data().normals) {
@@ -173,11 +213,11 @@ impl CollisionDetector {
                 });
             let projected_allowance = project_point(self.allowance, *angle);
 
-            if a_max - projected_allowance < b_min || b_max - projected_allowance < a_min {
-                return false;
-            }
+            separation = separation
+                .max(b_min - (a_max - projected_allowance))
+                .max(a_min - (b_max - projected_allowance));
         }
-        true
+        separation
     }
 }
 
@@ -207,12 +247,12 @@ struct SegmentsIterator {
 }
 
 impl SegmentsIterator {
-    pub fn new(so: &SceneObject) -> SegmentsIterator {
+    pub fn new(so: &SceneObject, margin: f32) -> SegmentsIterator {
         let hitbox = so.collision_data().hitbox();
-        let from_point = segment_coords(FPoint::new(so.pos.x() - hitbox.left,
-                                                    so.pos.y() - hitbox.top));
-        let to_point = segment_coords(FPoint::new(so.pos.x() + hitbox.right,
-                                                  so.pos.y() + hitbox.bottom));
+        let from_point = segment_coords(FPoint::new(so.pos.x() - hitbox.left - margin,
+                                                    so.pos.y() - hitbox.top - margin));
+        let to_point = segment_coords(FPoint::new(so.pos.x() + hitbox.right + margin,
+                                                  so.pos.y() + hitbox.bottom + margin));
         SegmentsIterator {
             from_point: from_point,
             cur_point: from_point,
@@ -241,3 +281,82 @@ impl Iterator for SegmentsIterator {
         Some(segment_no(result))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use scene::{ObjectType, PlayerState, SpriteDataCache};
+    use sprites::SpritesData;
+
+    const DISTANCE: f32 = 0.03;
+
+    // Bullet placed diagonally from the player so that the hull edges rather than the hitboxes
+    // decide the separation.
+    fn objects(cache: &SpriteDataCache, offset: f32) -> (SceneObject, SceneObject) {
+        let player = SceneObject::new(cache,
+                                      ObjectType::Player(PlayerState::Normal),
+                                      (0.5, 0.5),
+                                      Angle::from_deg(0.0),
+                                      (1.0, 1.0));
+        let bullet = SceneObject::new(cache,
+                                      ObjectType::EnemyBullet(0),
+                                      (0.5 + offset * 0.8, 0.5 + offset * 0.6),
+                                      Angle::from_deg(30.0),
+                                      (1.0, 1.0));
+        (player, bullet)
+    }
+
+    fn offset_with_separation(detector: &CollisionDetector,
+                              cache: &SpriteDataCache,
+                              separation: f32)
+                              -> f32 {
+        let (mut low, mut high) = (0.0, 0.5);
+        for _ in 0..40 {
+            let offset = (low + high) / 2.0;
+            let (player, bullet) = objects(cache, offset);
+            if detector.convex_separation(&bullet, &player) < separation {
+                low = offset;
+            } else {
+                high = offset;
+            }
+        }
+        low
+    }
+
+    // Returns whether the pair collided and whether it was a near miss.
+    fn detect(detector: &CollisionDetector, cache: &SpriteDataCache, offset: f32) -> (bool, bool) {
+        let (mut player, mut bullet) = objects(cache, offset);
+        let collided = Cell::new(false);
+        let near_missed = Cell::new(false);
+        detector.detect_collisions(Some(&mut bullet),
+                                   Some(&mut player),
+                                   |_, _| collided.set(true));
+        detector.detect_near_misses(Some(&mut bullet),
+                                    Some(&mut player),
+                                    DISTANCE,
+                                    |_, _| near_missed.set(true));
+        (collided.get(), near_missed.get())
+    }
+
+    #[test]
+    fn detects_collisions_and_near_misses() {
+        let sprites = SpritesData::new((200, 200));
+        let cache = SpriteDataCache::new(&sprites);
+        let detector = CollisionDetector::new(sprites.virtual_dimensions());
+
+        let touching = offset_with_separation(&detector, &cache, 0.0);
+        assert!(touching > 0.0 && touching < 0.5);
+        let cases = [(0.0, (true, false)),
+                     (touching / 2.0, (true, false)),
+                     (touching, (true, false)),
+                     (offset_with_separation(&detector, &cache, DISTANCE * 0.02), (false, true)),
+                     (offset_with_separation(&detector, &cache, DISTANCE * 0.5), (false, true)),
+                     (offset_with_separation(&detector, &cache, DISTANCE * 0.98), (false, true)),
+                     (offset_with_separation(&detector, &cache, DISTANCE * 1.02), (false, false)),
+                     (0.5, (false, false))];
+        for &(offset, expected) in &cases {
+            assert_eq!(detect(&detector, &cache, offset), expected, "offset {}", offset);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use game::GameState;
+use scene::{PlayerStatus, Scene};
+use util::{Dimensions, IPoint};
+use display::text::{Align, Text, GLYPH_WIDTH, GLYPH_HEIGHT};
+
+pub const ICON_SIZE: i32 = 6;
+const MARGIN: i32 = 2;
+const ROW_SPACING: i32 = GLYPH_HEIGHT + 1;
+const ICON_SPACING: i32 = ICON_SIZE + 1;
+const PLAYER_ROWS: i32 = 4;
+const MAX_ICONS: u32 = 8;
+const BOSS_LABEL: &'static str = "BOSS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconKind {
+    Life,
+    Bomb,
+    BarBackground,
+    BarFill,
+}
+
+// An icons atlas frame stretched over a rectangle in virtual pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct Icon {
+    pub kind: IconKind,
+    pub pos: IPoint,
+    pub size: IPoint,
+}
+
+impl Icon {
+    pub fn new<P1, P2>(kind: IconKind, pos: P1, size: P2) -> Icon
+        where P1: Into<IPoint>,
+              P2: Into<IPoint>
+    {
+        Icon {
+            kind: kind,
+            pos: pos.into(),
+            size: size.into(),
+        }
+    }
+}
+
+fn push_icon_row(icons: &mut Vec<Icon>, kind: IconKind, count: u32, pos: IPoint, align: Align) {
+    let count = count.min(MAX_ICONS) as i32;
+    let left = match align {
+        Align::Left => pos.x(),
+        Align::Center => pos.x() - count * ICON_SPACING / 2,
+        Align::Right => pos.x() - count * ICON_SPACING,
+    };
+    for i in 0..count {
+        icons.push(Icon::new(kind, (left + i * ICON_SPACING, pos.y()), (ICON_SIZE, ICON_SIZE)));
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Hud {
+    high_score: u64,
+    fps: Option<f32>,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Default::default()
+    }
+
+    pub fn set_high_score(&mut self, high_score: u64) {
+        self.high_score = high_score;
+    }
+
+    pub fn set_fps(&mut self, fps: Option<f32>) {
+        self.fps = fps;
+    }
+
+    pub fn layout(&self,
+                  scene: &Scene,
+                  state: GameState,
+                  dimensions: Dimensions)
+                  -> (Vec<Text>, Vec<Icon>) {
+        let width = dimensions.width() as i32;
+        let height = dimensions.height() as i32;
+        let mut texts = vec![];
+        let mut icons = vec![];
+
+        let best_score = scene.players().iter().map(|player| player.score()).max();
+        let high_score = self.high_score.max(best_score.unwrap_or(0));
+        texts.push(Text::new(format!("HI {:07}", high_score), (width / 2, MARGIN), Align::Center));
+        if let Some(fps) = self.fps {
+            texts.push(Text::new(format!("{:.0} FPS {} OBJ", fps, scene.total_objects()),
+                                 (width / 2, MARGIN + ROW_SPACING),
+                                 Align::Center));
+        }
+        if state == GameState::Title {
+            return (texts, icons);
+        }
+
+        // Even players take the left edge and odd players the right one.
+        for (i, player) in scene.players().iter().enumerate() {
+            let (x, align) = if i % 2 == 0 {
+                (MARGIN, Align::Left)
+            } else {
+                (width - MARGIN, Align::Right)
+            };
+            let top = MARGIN + (i / 2) as i32 * PLAYER_ROWS * ROW_SPACING;
+            texts.push(Text::new(format!("{}P {:07}", i + 1, player.score()), (x, top), align));
+            if player.status() == PlayerStatus::GameOver {
+                continue;
+            }
+            push_icon_row(&mut icons,
+                          IconKind::Life,
+                          player.lives(),
+                          IPoint::new(x, top + ROW_SPACING),
+                          align);
+            push_icon_row(&mut icons,
+                          IconKind::Bomb,
+                          player.bombs(),
+                          IPoint::new(x, top + 2 * ROW_SPACING),
+                          align);
+            let power = if player.weapon_level() >= scene.max_weapon_level() {
+                "POWER MAX".to_string()
+            } else {
+                format!("POWER {}", player.weapon_level() + 1)
+            };
+            texts.push(Text::new(power, (x, top + 3 * ROW_SPACING), align));
+        }
+
+        if let Some(health) = scene.boss_health() {
+            let top = height - MARGIN - ICON_SIZE;
+            texts.push(Text::new(BOSS_LABEL, (MARGIN, top), Align::Left));
+            let left = MARGIN * 2 + BOSS_LABEL.len() as i32 * GLYPH_WIDTH;
+            let bar_width = width - MARGIN - left;
+            let fill_width = (bar_width as f32 * health.max(0.0).min(1.0)).round() as i32;
+            icons.push(Icon::new(IconKind::BarBackground,
+                                 (left, top + 1),
+                                 (bar_width, ICON_SIZE - 2)));
+            if fill_width > 0 {
+                icons.push(Icon::new(IconKind::BarFill,
+                                     (left, top + 1),
+                                     (fill_width, ICON_SIZE - 2)));
+            }
+        }
+        (texts, icons)
+    }
+}
@@ -0,0 +1,69 @@
+use game::{Game, GameState};
+use scene::Scene;
+use util::Dimensions;
+use display::text::{Align, Text, GLYPH_HEIGHT};
+
+const TITLE: &'static str = "YAVSS";
+const BLINKS_PER_SECOND: f32 = 2.0;
+const LINE_SPACING: i32 = GLYPH_HEIGHT + 2;
+
+fn blink_on(time: f32) -> bool {
+    (time * BLINKS_PER_SECOND) as u32 % 2 == 0
+}
+
+// Centered lines of text, with the block of lines centered on the screen.
+fn centered_lines(lines: &[&str], dimensions: Dimensions) -> Vec<Text> {
+    let center_x = dimensions.width() as i32 / 2;
+    let top = (dimensions.height() as i32 - lines.len() as i32 * LINE_SPACING) / 2;
+    lines
+        .iter()
+        .enumerate()
+        .filter(|&(_, line)| !line.is_empty())
+        .map(|(i, line)| Text::new(*line, (center_x, top + i as i32 * LINE_SPACING), Align::Center))
+        .collect()
+}
+
+pub fn menu_texts(game: &Game,
+                  scene: &Scene,
+                  prompt: Option<&str>,
+                  dimensions: Dimensions)
+                  -> Vec<Text> {
+    let blink = blink_on(game.state_time());
+    let mut texts = match game.state() {
+        GameState::Title => {
+            centered_lines(&[TITLE, "", "", if blink { "PRESS FIRE TO START" } else { "" }],
+                           dimensions)
+        }
+        GameState::Paused => {
+            if game.has_title_screen() {
+                centered_lines(&["PAUSED", "", "PAUSE - RESUME", "BOMB - QUIT TO TITLE"],
+                               dimensions)
+            } else {
+                centered_lines(&["PAUSED"], dimensions)
+            }
+        }
+        GameState::GameOver => centered_lines(&["GAME OVER"], dimensions),
+        GameState::Playing => {
+            let continues = scene
+                .players()
+                .iter()
+                .enumerate()
+                .filter_map(|(i, player)| {
+                    player
+                        .continue_timeout()
+                        .map(|timeout| format!("P{} CONTINUE? {}", i + 1, timeout.ceil() as u32))
+                })
+                .collect::<Vec<_>>();
+            let mut lines = continues.iter().map(|line| line.as_str()).collect::<Vec<_>>();
+            if !lines.is_empty() {
+                lines.push(if blink { "PRESS FIRE" } else { "" });
+            }
+            centered_lines(&lines, dimensions)
+        }
+    };
+    if let Some(prompt) = prompt {
+        let bottom = dimensions.height() as i32 - 2 * LINE_SPACING;
+        texts.push(Text::new(prompt, (dimensions.width() as i32 / 2, bottom), Align::Center));
+    }
+    texts
+}
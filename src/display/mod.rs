@@ -1,16 +1,29 @@
 use glium;
+use game::GameState;
 use scene;
 use util::Dimensions;
 
 mod background;
 mod sprites;
 mod postprocessor;
+mod text;
+mod menu;
+mod hud;
+
+pub use self::text::{Align, Text};
+pub use self::menu::menu_texts;
+
+const TITLE_DIM: f32 = 0.6;
+const PAUSE_DIM: f32 = 0.5;
+const GAME_OVER_DIM: f32 = 0.4;
 
 pub struct Renderer {
     background: background::Background,
     sprites: sprites::Sprites,
     sprites_texture: glium::texture::SrgbTexture2d,
     postprocessor: postprocessor::PostProcessor,
+    hud: hud::Hud,
+    virtual_dimensions: Dimensions,
 }
 
 impl Renderer {
@@ -21,6 +34,7 @@ impl Renderer {
         where F: glium::backend::Facade,
               D: Into<Dimensions>
     {
+        let virtual_dimensions = virtual_dimensions.into();
         let glium_image =
             glium::texture::RawImage2d::from_raw_rgba_reversed(sprites_data.image_buffer(),
                                                                sprites_data.image_size().into());
@@ -31,13 +45,33 @@ impl Renderer {
             sprites: sprites::Sprites::new(facade, sprites_data),
             sprites_texture: texture,
             postprocessor: postprocessor::PostProcessor::new(facade, virtual_dimensions),
+            hud: hud::Hud::new(),
+            virtual_dimensions: virtual_dimensions,
         }
     }
 
+    pub fn set_high_score(&mut self, high_score: u64) {
+        self.hud.set_high_score(high_score);
+    }
+
+    pub fn set_fps(&mut self, fps: Option<f32>) {
+        self.hud.set_fps(fps);
+    }
+
     pub fn render(&mut self,
                   window: &glium::backend::glutin_backend::GlutinFacade,
-                  scene: &scene::Scene) {
+                  scene: &scene::Scene,
+                  state: GameState,
+                  texts: &[Text],
+                  alpha: f32) {
         use glium::Surface;
+        let dim = match state {
+            GameState::Title => TITLE_DIM,
+            GameState::Playing => 0.0,
+            GameState::Paused => PAUSE_DIM,
+            GameState::GameOver => GAME_OVER_DIM,
+        };
+        let (hud_texts, hud_icons) = self.hud.layout(scene, state, self.virtual_dimensions);
         let mut surface = window.draw();
         self.postprocessor
             .draw(|framebuffer| {
@@ -45,11 +79,22 @@ impl Renderer {
                 self.background
                     .render(framebuffer,
                             &self.sprites_texture,
-                            scene.background_position());
+                            scene.interpolated_background_position(alpha));
+                self.sprites
+                    .render(window, framebuffer, &self.sprites_texture, scene, alpha);
+                self.sprites
+                    .render_overlay(window,
+                                    framebuffer,
+                                    &self.sprites_texture,
+                                    &hud_texts,
+                                    &hud_icons);
+                if dim > 0.0 {
+                    self.postprocessor.dim(framebuffer, dim);
+                }
                 self.sprites
-                    .render(window, framebuffer, &self.sprites_texture, scene);
+                    .render_overlay(window, framebuffer, &self.sprites_texture, texts, &[]);
             });
-        self.postprocessor.render(&mut surface);
+        self.postprocessor.render(&mut surface, scene);
         surface.finish().expect("Can't draw on a surface");
     }
 }
@@ -1,10 +1,13 @@
 use glium;
+use scene;
 use util::Dimensions;
 
 const POSTPROCESSOR_VERTEX_SHADER: &'static str = include_str!("../shaders/v_post.glsl");
 
 const POSTPROCESSOR_FRAGMENT_SHADER: &'static str = include_str!("../shaders/f_post.glsl");
 
+const DIM_FRAGMENT_SHADER: &'static str = include_str!("../shaders/f_dim.glsl");
+
 #[derive(Copy, Clone)]
 struct PostprocessorVertex {
     v_pos: [f32; 2],
@@ -34,6 +37,7 @@ const POSTPROCESSOR_INDICES: [u16; 6] = [0, 1, 2, 0, 3, 2];
 
 pub struct PostProcessor {
     program: glium::Program,
+    dim_program: glium::Program,
     texture: glium::texture::Texture2d,
     shape: glium::VertexBuffer<PostprocessorVertex>,
     indices: glium::IndexBuffer<u16>,
@@ -52,6 +56,11 @@ impl PostProcessor {
                                                  POSTPROCESSOR_FRAGMENT_SHADER,
                                                  None)
                 .expect("Can't initialize program"),
+            dim_program: glium::Program::from_source(facade,
+                                                     POSTPROCESSOR_VERTEX_SHADER,
+                                                     DIM_FRAGMENT_SHADER,
+                                                     None)
+                .expect("Can't initialize program"),
             texture: glium::texture::Texture2d::empty(facade,
                                                       dimensions.width(),
                                                       dimensions.height())
@@ -72,9 +81,30 @@ impl PostProcessor {
         f(&mut self.texture.as_surface())
     }
 
-    pub fn render<S>(&self, surface: &mut S)
+    // Darkens everything drawn so far, so menus can be drawn on top of a frozen scene.
+    pub fn dim<S>(&self, surface: &mut S, dim: f32)
+        where S: glium::Surface
+    {
+        let draw_parameters = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            ..Default::default()
+        };
+        surface
+            .draw(&self.shape,
+                  &self.indices,
+                  &self.dim_program,
+                  &uniform! { u_dim: dim },
+                  &draw_parameters)
+            .expect("Can't draw");
+    }
+
+    pub fn render<S>(&self, surface: &mut S, scene: &scene::Scene)
         where S: glium::Surface
     {
+        let (bomb_center, bomb_progress) = match scene.bomb_effect() {
+            Some((pos, progress)) => ([pos.x(), 1.0 - pos.y()], progress),
+            None => ([0.0, 0.0], 0.0),
+        };
         let uniforms = uniform! {
             t_post: self.texture.sampled()
                 .anisotropy(1)
@@ -83,6 +113,8 @@ impl PostProcessor {
                 .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest),
             u_virtual_width: self.virtual_dimensions.x(),
             u_virtual_height: self.virtual_dimensions.y(),
+            u_bomb_center: bomb_center,
+            u_bomb_progress: bomb_progress,
         };
         surface
             .draw(&self.shape,
@@ -1,6 +1,9 @@
 use glium;
 use scene::Scene;
 use sprites::SpriteObject;
+use util::{FDimensions, FPoint, IPoint};
+use display::text::{Text, GLYPH_WIDTH, GLYPH_HEIGHT};
+use display::hud::{Icon, ICON_SIZE};
 
 const MAX_SPRITES_COUNT: usize = 1024;
 
@@ -16,7 +19,7 @@ struct SpriteVertex {
 implement_vertex!(SpriteVertex, v_pos, v_transform, v_sprite, v_frame, v_scale);
 
 impl SpriteVertex {
-    fn from_scene_object(scene_object: &::scene::SceneObject) -> SpriteVertex {
+    fn from_scene_object(scene_object: &::scene::SceneObject, alpha: f32) -> SpriteVertex {
         use scene::{PlayerState, ObjectType};
         use cgmath;
         let (sprite, frame) = match scene_object.object_type {
@@ -25,6 +28,12 @@ impl SpriteVertex {
             ObjectType::Player(PlayerState::TiltedRight) => (SpriteObject::Player as u32, 2),
             ObjectType::PlayerBullet(frame) => (SpriteObject::PlayerBullet as u32, frame % 4),
             ObjectType::EnemyBullet(frame) => (SpriteObject::EnemyBullet as u32, frame % 4),
+            ObjectType::Enemy => (SpriteObject::Enemy as u32, 0),
+            ObjectType::Boss => (SpriteObject::Boss as u32, 0),
+            ObjectType::Explosion(frame) => (SpriteObject::Explosion as u32, frame),
+            ObjectType::ScoreItem(frame) => (SpriteObject::ScoreItem as u32, frame % 4),
+            ObjectType::PowerItem(frame) => (SpriteObject::PowerItem as u32, frame % 4),
+            ObjectType::Hitbox => (SpriteObject::Hitbox as u32, 0),
         };
         let transform = cgmath::Matrix4::from(cgmath::Quaternion::from(cgmath::Euler {
                 x: cgmath::Deg(0.0),
@@ -33,13 +42,39 @@ impl SpriteVertex {
             }))
             .into();
         SpriteVertex {
-            v_pos: [scene_object.pos.x(), scene_object.pos.y()],
+            v_pos: scene_object.interpolated_pos(alpha).into(),
             v_sprite: sprite,
             v_frame: frame,
             v_transform: transform,
             v_scale: scene_object.sprite_scale().into(),
         }
     }
+
+    fn from_glyph(frame: u32, pos: IPoint, virtual_dimensions: FDimensions) -> SpriteVertex {
+        use cgmath::{self, SquareMatrix};
+        let half_glyph = FPoint::new(GLYPH_WIDTH as f32 / 2.0, GLYPH_HEIGHT as f32 / 2.0);
+        let center = pos.as_f32() + half_glyph;
+        SpriteVertex {
+            v_pos: (center / virtual_dimensions).into(),
+            v_sprite: SpriteObject::Font as u32,
+            v_frame: frame,
+            v_transform: cgmath::Matrix4::identity().into(),
+            v_scale: [1.0, 1.0],
+        }
+    }
+
+    fn from_icon(icon: &Icon, virtual_dimensions: FDimensions) -> SpriteVertex {
+        use cgmath::{self, SquareMatrix};
+        let size = icon.size.as_f32();
+        let center = icon.pos.as_f32() + size / FPoint::new(2.0, 2.0);
+        SpriteVertex {
+            v_pos: (center / virtual_dimensions).into(),
+            v_sprite: SpriteObject::Icons as u32,
+            v_frame: icon.kind as u32,
+            v_transform: cgmath::Matrix4::identity().into(),
+            v_scale: (size / FPoint::new(ICON_SIZE as f32, ICON_SIZE as f32)).into(),
+        }
+    }
 }
 
 const SPRITE_VERTEX_SHADER: &'static str = include_str!("../shaders/v_sprites.glsl");
@@ -53,6 +88,7 @@ pub struct Sprites {
     offsets_ub: glium::uniforms::UniformBuffer<[[f32; 2]; MAX_SPRITES_COUNT]>,
     dimensions_ub: glium::uniforms::UniformBuffer<[[f32; 2]; MAX_SPRITES_COUNT]>,
     program: glium::Program,
+    virtual_dimensions: FDimensions,
 }
 
 impl Sprites {
@@ -91,6 +127,7 @@ impl Sprites {
             sizes_ub: sizes_ub,
             offsets_ub: offsets_ub,
             dimensions_ub: dimensions_ub,
+            virtual_dimensions: sprites_data.virtual_dimensions().as_f32(),
         }
     }
 
@@ -98,15 +135,51 @@ impl Sprites {
                         facade: &F,
                         surface: &mut S,
                         sprites_texture: &glium::texture::SrgbTexture2d,
-                        scene: &Scene)
+                        scene: &Scene,
+                        alpha: f32)
         where S: glium::Surface,
               F: glium::backend::Facade
     {
         let vertices: Vec<SpriteVertex> = scene
             .objects()
-            .map(SpriteVertex::from_scene_object)
+            .map(|scene_object| SpriteVertex::from_scene_object(scene_object, alpha))
             .collect();
-        let vertex_buffer = glium::vertex::VertexBuffer::new(facade, &vertices)
+        self.draw(facade, surface, sprites_texture, &vertices);
+    }
+
+    pub fn render_overlay<S, F>(&self,
+                                facade: &F,
+                                surface: &mut S,
+                                sprites_texture: &glium::texture::SrgbTexture2d,
+                                texts: &[Text],
+                                icons: &[Icon])
+        where S: glium::Surface,
+              F: glium::backend::Facade
+    {
+        let vertices: Vec<SpriteVertex> = icons
+            .iter()
+            .map(|icon| SpriteVertex::from_icon(icon, self.virtual_dimensions))
+            .chain(texts
+                       .iter()
+                       .flat_map(|text| text.glyphs())
+                       .map(|(frame, pos)| {
+                                SpriteVertex::from_glyph(frame, pos, self.virtual_dimensions)
+                            }))
+            .collect();
+        if !vertices.is_empty() {
+            self.draw(facade, surface, sprites_texture, &vertices);
+        }
+    }
+
+    fn draw<S, F>(&self,
+                  facade: &F,
+                  surface: &mut S,
+                  sprites_texture: &glium::texture::SrgbTexture2d,
+                  vertices: &[SpriteVertex])
+        where S: glium::Surface,
+              F: glium::backend::Facade
+    {
+        let vertex_buffer = glium::vertex::VertexBuffer::new(facade, vertices)
             .expect("Can't initialize vertex buffer");
         surface
             .draw(&vertex_buffer,
@@ -0,0 +1,70 @@
+use util::IPoint;
+
+pub const GLYPH_WIDTH: i32 = 4;
+pub const GLYPH_HEIGHT: i32 = 6;
+const FIRST_GLYPH: char = ' ';
+const LAST_GLYPH: char = '_';
+const UNKNOWN_GLYPH: char = '?';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// A line of text anchored at a point in virtual pixels, top edge at `pos.y()`.
+#[derive(Debug, Clone)]
+pub struct Text {
+    text: String,
+    pos: IPoint,
+    align: Align,
+}
+
+impl Text {
+    pub fn new<S, P>(text: S, pos: P, align: Align) -> Text
+        where S: Into<String>,
+              P: Into<IPoint>
+    {
+        Text {
+            text: text.into(),
+            pos: pos.into(),
+            align: align,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn width(&self) -> i32 {
+        self.text.chars().count() as i32 * GLYPH_WIDTH
+    }
+
+    // Font frames with the top left corner of every glyph in virtual pixels.
+    pub fn glyphs(&self) -> Vec<(u32, IPoint)> {
+        let left = match self.align {
+            Align::Left => self.pos.x(),
+            Align::Center => self.pos.x() - self.width() / 2,
+            Align::Right => self.pos.x() - self.width(),
+        };
+        self.text
+            .chars()
+            .enumerate()
+            .filter(|&(_, c)| c != ' ')
+            .map(|(i, c)| {
+                     (glyph_frame(c), IPoint::new(left + i as i32 * GLYPH_WIDTH, self.pos.y()))
+                 })
+            .collect()
+    }
+}
+
+pub fn glyph_frame(c: char) -> u32 {
+    let c = c.to_ascii_uppercase();
+    let c = if c >= FIRST_GLYPH && c <= LAST_GLYPH {
+        c
+    } else {
+        UNKNOWN_GLYPH
+    };
+    c as u32 - FIRST_GLYPH as u32
+}
@@ -0,0 +1,269 @@
+use std::time::Duration;
+use input::Controls;
+use util::seconds;
+
+const GAME_OVER_DURATION: f32 = 5.0;
+const GAME_OVER_SKIP_DELAY: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+    Title,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Idle,
+    Tick,
+    NewGame,
+}
+
+#[derive(Debug, Default)]
+struct Buttons {
+    fire: bool,
+    bomb: bool,
+    pause: bool,
+}
+
+impl Buttons {
+    fn from_inputs<C>(inputs: &[C]) -> Buttons
+        where C: Controls
+    {
+        Buttons {
+            fire: inputs.iter().any(|input| input.fire_is_pressed()),
+            bomb: inputs.iter().any(|input| input.bomb_is_pressed()),
+            pause: inputs.iter().any(|input| input.pause_is_pressed()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Game {
+    state: GameState,
+    title_screen: bool,
+    pause_enabled: bool,
+    state_time: f32,
+    buttons: Buttons,
+}
+
+impl Game {
+    pub fn new(title_screen: bool) -> Game {
+        Game {
+            state: if title_screen {
+                GameState::Title
+            } else {
+                GameState::Playing
+            },
+            title_screen: title_screen,
+            pause_enabled: true,
+            state_time: 0.0,
+            buttons: Default::default(),
+        }
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    pub fn state_time(&self) -> f32 {
+        self.state_time
+    }
+
+    pub fn has_title_screen(&self) -> bool {
+        self.title_screen
+    }
+
+    pub fn set_pause_enabled(&mut self, enabled: bool) {
+        self.pause_enabled = enabled;
+    }
+
+    // Buttons only count on the tick they get pressed, so a held fire button can't skip screens.
+    pub fn update<C>(&mut self, inputs: &[C], game_over: bool, duration: Duration) -> Step
+        where C: Controls
+    {
+        let duration_s = seconds(duration);
+        let buttons = Buttons::from_inputs(inputs);
+        let fire = buttons.fire && !self.buttons.fire;
+        let bomb = buttons.bomb && !self.buttons.bomb;
+        let pause = buttons.pause && !self.buttons.pause;
+        self.buttons = buttons;
+        self.state_time += duration_s;
+        match self.state {
+            GameState::Title => {
+                if fire || pause {
+                    self.set_state(GameState::Playing);
+                    return Step::NewGame;
+                }
+                Step::Idle
+            }
+            GameState::Playing => {
+                if game_over {
+                    self.set_state(GameState::GameOver);
+                } else if pause && self.pause_enabled {
+                    self.set_state(GameState::Paused);
+                    return Step::Idle;
+                }
+                Step::Tick
+            }
+            GameState::Paused => {
+                if pause {
+                    self.set_state(GameState::Playing);
+                } else if bomb && self.title_screen {
+                    self.set_state(GameState::Title);
+                }
+                Step::Idle
+            }
+            GameState::GameOver => {
+                if !game_over {
+                    self.set_state(GameState::Playing);
+                    return Step::Tick;
+                }
+                let skipped = (fire || pause) && self.state_time >= GAME_OVER_SKIP_DELAY;
+                if self.title_screen && (skipped || self.state_time >= GAME_OVER_DURATION) {
+                    self.set_state(GameState::Title);
+                    return Step::Idle;
+                }
+                Step::Tick
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: GameState) {
+        self.state = state;
+        self.state_time = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::AxisValue;
+
+    #[derive(Default)]
+    struct Pressed {
+        fire: bool,
+        bomb: bool,
+        pause: bool,
+    }
+
+    impl Controls for Pressed {
+        fn x_move(&self) -> AxisValue {
+            0.0
+        }
+
+        fn y_move(&self) -> AxisValue {
+            0.0
+        }
+
+        fn left_tilt(&self) -> AxisValue {
+            0.0
+        }
+
+        fn right_tilt(&self) -> AxisValue {
+            0.0
+        }
+
+        fn fire_is_pressed(&self) -> bool {
+            self.fire
+        }
+
+        fn focus_is_pressed(&self) -> bool {
+            false
+        }
+
+        fn bomb_is_pressed(&self) -> bool {
+            self.bomb
+        }
+
+        fn pause_is_pressed(&self) -> bool {
+            self.pause
+        }
+    }
+
+    const NONE: Pressed = Pressed { fire: false, bomb: false, pause: false };
+    const FIRE: Pressed = Pressed { fire: true, bomb: false, pause: false };
+    const PAUSE: Pressed = Pressed { fire: false, bomb: false, pause: true };
+
+    fn tick() -> Duration {
+        Duration::from_millis(250)
+    }
+
+    fn update(game: &mut Game, input: Pressed, game_over: bool) -> Step {
+        game.update(&[input], game_over, tick())
+    }
+
+    #[test]
+    fn title_starts_new_game_on_fire() {
+        let mut game = Game::new(true);
+        assert_eq!(update(&mut game, NONE, false), Step::Idle);
+        assert_eq!(game.state(), GameState::Title);
+        assert_eq!(update(&mut game, FIRE, false), Step::NewGame);
+        assert_eq!(game.state(), GameState::Playing);
+    }
+
+    #[test]
+    fn held_fire_does_not_skip_screens() {
+        let mut game = Game::new(true);
+        assert_eq!(update(&mut game, FIRE, false), Step::NewGame);
+        update(&mut game, FIRE, true);
+        assert_eq!(game.state(), GameState::GameOver);
+        for _ in 0..10 {
+            assert_eq!(update(&mut game, FIRE, true), Step::Tick);
+        }
+        assert_eq!(game.state(), GameState::GameOver);
+        update(&mut game, NONE, true);
+        update(&mut game, FIRE, true);
+        assert_eq!(game.state(), GameState::Title);
+        assert_eq!(update(&mut game, FIRE, false), Step::Idle);
+        assert_eq!(game.state(), GameState::Title);
+    }
+
+    #[test]
+    fn pause_toggles() {
+        let mut game = Game::new(false);
+        assert_eq!(update(&mut game, NONE, false), Step::Tick);
+        assert_eq!(update(&mut game, PAUSE, false), Step::Idle);
+        assert_eq!(game.state(), GameState::Paused);
+        assert_eq!(update(&mut game, PAUSE, false), Step::Idle);
+        assert_eq!(game.state(), GameState::Paused);
+        update(&mut game, NONE, false);
+        update(&mut game, PAUSE, false);
+        assert_eq!(game.state(), GameState::Playing);
+        assert_eq!(update(&mut game, NONE, false), Step::Tick);
+    }
+
+    #[test]
+    fn pause_ignored_when_disabled() {
+        let mut game = Game::new(false);
+        game.set_pause_enabled(false);
+        assert_eq!(update(&mut game, PAUSE, false), Step::Tick);
+        assert_eq!(game.state(), GameState::Playing);
+    }
+
+    #[test]
+    fn game_over_returns_to_title() {
+        let mut game = Game::new(true);
+        update(&mut game, FIRE, false);
+        update(&mut game, NONE, true);
+        assert_eq!(game.state(), GameState::GameOver);
+        let ticks = (GAME_OVER_DURATION / seconds(tick())).round() as usize;
+        for _ in 1..ticks {
+            assert_eq!(update(&mut game, NONE, true), Step::Tick);
+        }
+        assert_eq!(game.state(), GameState::GameOver);
+        assert_eq!(update(&mut game, NONE, true), Step::Idle);
+        assert_eq!(game.state(), GameState::Title);
+    }
+
+    #[test]
+    fn game_over_continues_playing() {
+        let mut game = Game::new(true);
+        update(&mut game, FIRE, false);
+        update(&mut game, NONE, true);
+        assert_eq!(game.state(), GameState::GameOver);
+        assert_eq!(update(&mut game, NONE, false), Step::Tick);
+        assert_eq!(game.state(), GameState::Playing);
+    }
+}
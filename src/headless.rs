@@ -0,0 +1,215 @@
+use std::time::{Duration, Instant};
+use input::{self, InputSource, InputState};
+use netplay::{self, LocalTransport, LossyTransport, Session};
+use replay::Replay;
+use scene::Scene;
+use sprites::SpritesData;
+use util::seconds;
+
+const DEFAULT_TICKS: usize = 60 * 60 * 2;
+const SWEEP_TICKS: usize = 120;
+
+pub struct RunStats<'a> {
+    pub scene: Scene<'a>,
+    pub ticks: usize,
+    pub real_duration: Duration,
+}
+
+pub struct PeerStats {
+    pub rollbacks: usize,
+    pub resimulated_ticks: usize,
+    pub stalls: usize,
+    pub synchronized: bool,
+    pub checksum: u64,
+}
+
+pub struct NetplayStats {
+    pub ticks: usize,
+    pub frames: usize,
+    pub real_duration: Duration,
+    pub peers: Vec<PeerStats>,
+    pub local_checksum: u64,
+}
+
+impl NetplayStats {
+    pub fn in_sync(&self) -> bool {
+        self.peers.iter().all(|peer| peer.checksum == self.local_checksum)
+    }
+}
+
+pub fn run<'a, I>(sprites: &'a SpritesData,
+                  seed: u64,
+                  players: usize,
+                  tick_duration: Duration,
+                  ticks: Option<usize>,
+                  input: &mut I,
+                  mut recording: Option<&mut Replay>)
+                  -> RunStats<'a>
+    where I: InputSource + ?Sized
+{
+    let mut scene = Scene::with_players(sprites, seed, players);
+    let instant = Instant::now();
+
+    let mut tick = 0;
+    while ticks.map_or(true, |ticks| tick < ticks) && !scene.game_over() {
+        input.poll_events();
+        if input.exit() {
+            break;
+        }
+        let inputs = input::player_inputs(input, players);
+        if let Some(ref mut recording) = recording {
+            recording.record(&inputs);
+        }
+        scene.tick(&inputs, tick_duration);
+        tick += 1;
+    }
+
+    RunStats {
+        scene: scene,
+        ticks: tick,
+        real_duration: Instant::now() - instant,
+    }
+}
+
+// Runs a host and a client session in-process over a lossy link and compares both with a local
+// simulation fed with the same delayed inputs.
+pub fn run_netplay_test(sprites: &SpritesData,
+                        seed: u64,
+                        tick_duration: Duration,
+                        ticks: Option<usize>,
+                        input_delay: usize,
+                        latency: Duration,
+                        jitter: Duration,
+                        loss: f32)
+                        -> Result<NetplayStats, String> {
+    let ticks = ticks.unwrap_or(DEFAULT_TICKS);
+    let script = |player: usize, tick: usize| {
+        scripted_input(tick + player * SWEEP_TICKS / 2).unwrap_or_default()
+    };
+    let instant = Instant::now();
+
+    let (host_link, client_link) = LocalTransport::pair();
+    let mut sessions = [Session::new(Box::new(LossyTransport::new(host_link,
+                                                                  latency,
+                                                                  jitter,
+                                                                  loss,
+                                                                  seed)),
+                                     0,
+                                     seed,
+                                     input_delay),
+                        Session::new(Box::new(LossyTransport::new(client_link,
+                                                                  latency,
+                                                                  jitter,
+                                                                  loss,
+                                                                  seed + 1)),
+                                     1,
+                                     seed,
+                                     input_delay)];
+    let mut scenes = [Scene::with_players(sprites, seed, netplay::PLAYERS),
+                      Scene::with_players(sprites, seed, netplay::PLAYERS)];
+    let mut frames = 0;
+    while sessions.iter().any(|session| session.tick() < ticks) {
+        for (session, scene) in sessions.iter_mut().zip(scenes.iter_mut()) {
+            let result = if session.tick() < ticks {
+                let input = script(session.local_player(), session.tick());
+                session.advance(scene, input, tick_duration).map(|_| ())
+            } else {
+                session.poll(scene, tick_duration)
+            };
+            result.map_err(|err| format!("peer {}: {}", session.local_player() + 1, err))?;
+        }
+        frames += 1;
+    }
+    let max_flush_frames = 10 * (1.0 + (seconds(latency) + seconds(jitter)) /
+                                       seconds(tick_duration)) as usize;
+    for _ in 0..max_flush_frames {
+        if sessions.iter().all(|session| session.is_synchronized()) {
+            break;
+        }
+        for (session, scene) in sessions.iter_mut().zip(scenes.iter_mut()) {
+            session.poll(scene, tick_duration)
+                .map_err(|err| format!("peer {}: {}", session.local_player() + 1, err))?;
+        }
+        frames += 1;
+    }
+
+    let mut reference = Scene::with_players(sprites, seed, netplay::PLAYERS);
+    for tick in 0..ticks {
+        let inputs = (0..netplay::PLAYERS)
+            .map(|player| if tick < input_delay {
+                     Default::default()
+                 } else {
+                     script(player, tick - input_delay)
+                 })
+            .collect::<Vec<_>>();
+        reference.tick(&inputs, tick_duration);
+    }
+
+    let peers = sessions.iter()
+        .zip(scenes.iter())
+        .map(|(session, scene)| {
+            PeerStats {
+                rollbacks: session.rollbacks(),
+                resimulated_ticks: session.resimulated_ticks(),
+                stalls: session.stalls(),
+                synchronized: session.is_synchronized(),
+                checksum: scene.checksum(),
+            }
+        })
+        .collect();
+    Ok(NetplayStats {
+        ticks: ticks,
+        frames: frames,
+        real_duration: Instant::now() - instant,
+        peers: peers,
+        local_checksum: reference.checksum(),
+    })
+}
+
+pub fn scripted_input(tick: usize) -> Option<InputState> {
+    if tick >= DEFAULT_TICKS {
+        return None;
+    }
+    let phase = (tick % SWEEP_TICKS) as f32 / SWEEP_TICKS as f32;
+    let x_move = if phase < 0.5 { 1.0 - phase * 4.0 } else { phase * 4.0 - 3.0 };
+    Some(InputState::new(x_move, 0.0, true, false, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_records_every_tick() {
+        let sprites = SpritesData::new((200, 200));
+        let mut recording = Replay::new(42, 2).expect("Can't create replay");
+        let stats = run(&sprites,
+                        42,
+                        2,
+                        Duration::new(0, 1_000_000_000 / 60),
+                        Some(120),
+                        &mut input::ScriptedInput::new(scripted_input),
+                        Some(&mut recording));
+        assert_eq!(stats.ticks, 120);
+        assert_eq!(stats.scene.players().len(), 2);
+        assert_eq!(recording.ticks(), 120);
+    }
+
+    #[test]
+    fn netplay_stays_in_sync_over_lossy_link() {
+        let sprites = SpritesData::new((200, 200));
+        let stats = run_netplay_test(&sprites,
+                                     42,
+                                     Duration::new(0, 1_000_000_000 / 60),
+                                     Some(300),
+                                     netplay::DEFAULT_INPUT_DELAY,
+                                     Duration::from_millis(50),
+                                     Duration::from_millis(30),
+                                     0.2)
+            .expect("Can't run netplay test");
+        assert_eq!(stats.ticks, 300);
+        assert_eq!(stats.peers.len(), netplay::PLAYERS);
+        assert!(stats.peers.iter().all(|peer| peer.synchronized));
+        assert!(stats.in_sync());
+    }
+}
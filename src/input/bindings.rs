@@ -0,0 +1,533 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use gilrs::{Axis, Button};
+use input::AxisValue;
+use input::response::{AxisResponse, ControllerResponse, DeadzoneKind};
+
+const DEFAULT_BINDINGS: &'static str = include_str!("../../data/bindings.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Fire,
+    Focus,
+    Bomb,
+    Pause,
+    Exit,
+    Rebind,
+    SaveState,
+    LoadState,
+}
+
+pub const REBINDABLE_ACTIONS: [Action; 8] = [Action::Up,
+                                              Action::Down,
+                                              Action::Left,
+                                              Action::Right,
+                                              Action::Fire,
+                                              Action::Focus,
+                                              Action::Bomb,
+                                              Action::Pause];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisAction {
+    MoveX,
+    MoveY,
+    LeftTilt,
+    RightTilt,
+}
+
+const ACTION_NAMES: [(Action, &'static str); 12] = [(Action::Up, "UP"),
+                                                    (Action::Down, "DOWN"),
+                                                    (Action::Left, "LEFT"),
+                                                    (Action::Right, "RIGHT"),
+                                                    (Action::Fire, "FIRE"),
+                                                    (Action::Focus, "FOCUS"),
+                                                    (Action::Bomb, "BOMB"),
+                                                    (Action::Pause, "PAUSE"),
+                                                    (Action::Exit, "EXIT"),
+                                                    (Action::Rebind, "REBIND"),
+                                                    (Action::SaveState, "SAVE_STATE"),
+                                                    (Action::LoadState, "LOAD_STATE")];
+
+const AXIS_ACTION_NAMES: [(AxisAction, &'static str); 4] = [(AxisAction::MoveX, "MOVE_X"),
+                                                            (AxisAction::MoveY, "MOVE_Y"),
+                                                            (AxisAction::LeftTilt, "LEFT_TILT"),
+                                                            (AxisAction::RightTilt, "RIGHT_TILT")];
+
+const BUTTON_NAMES: [(Button, &'static str); 19] = [(Button::South, "South"),
+                                                    (Button::East, "East"),
+                                                    (Button::North, "North"),
+                                                    (Button::West, "West"),
+                                                    (Button::C, "C"),
+                                                    (Button::Z, "Z"),
+                                                    (Button::LeftTrigger, "LeftTrigger"),
+                                                    (Button::LeftTrigger2, "LeftTrigger2"),
+                                                    (Button::RightTrigger, "RightTrigger"),
+                                                    (Button::RightTrigger2, "RightTrigger2"),
+                                                    (Button::Select, "Select"),
+                                                    (Button::Start, "Start"),
+                                                    (Button::Mode, "Mode"),
+                                                    (Button::LeftThumb, "LeftThumb"),
+                                                    (Button::RightThumb, "RightThumb"),
+                                                    (Button::DPadUp, "DPadUp"),
+                                                    (Button::DPadDown, "DPadDown"),
+                                                    (Button::DPadLeft, "DPadLeft"),
+                                                    (Button::DPadRight, "DPadRight")];
+
+const AXIS_NAMES: [(Axis, &'static str); 6] = [(Axis::LeftStickX, "LeftStickX"),
+                                               (Axis::LeftStickY, "LeftStickY"),
+                                               (Axis::LeftZ, "LeftZ"),
+                                               (Axis::RightStickX, "RightStickX"),
+                                               (Axis::RightStickY, "RightStickY"),
+                                               (Axis::RightZ, "RightZ")];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseTarget {
+    Movement,
+    Tilt,
+}
+
+const RESPONSE_TARGET_NAMES: [(ResponseTarget, &'static str); 2] =
+    [(ResponseTarget::Movement, "MOVE"), (ResponseTarget::Tilt, "TILT")];
+
+const DEADZONE_KIND_NAMES: [(DeadzoneKind, &'static str); 2] = [(DeadzoneKind::Radial, "RADIAL"),
+                                                               (DeadzoneKind::Axial, "AXIAL")];
+
+#[derive(Debug, Clone, Copy)]
+enum ResponseRule {
+    Axis(ResponseTarget, AxisResponse),
+    Snap(bool),
+}
+
+fn lookup<T, U>(table: &[(T, U)], key: &T) -> Option<U>
+    where T: PartialEq,
+          U: Copy
+{
+    table.iter().find(|&&(ref k, _)| k == key).map(|&(_, v)| v)
+}
+
+fn reverse_lookup<T>(table: &[(T, &'static str)], name: &str) -> Option<T>
+    where T: Copy
+{
+    table.iter().find(|&&(_, n)| n == name).map(|&(v, _)| v)
+}
+
+fn is_rebindable(action: Action) -> bool {
+    REBINDABLE_ACTIONS.contains(&action)
+}
+
+pub fn action_name(action: Action) -> &'static str {
+    lookup(&ACTION_NAMES, &action).expect("Can't find action name")
+}
+
+pub fn button_name(button: Button) -> Option<&'static str> {
+    lookup(&BUTTON_NAMES, &button)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AxisBinding {
+    pub action: AxisAction,
+    pub axis: Axis,
+    pub inverted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    keys: Vec<(Action, String)>,
+    buttons: Vec<(Action, Button)>,
+    axes: Vec<AxisBinding>,
+    responses: Vec<(ResponseRule, Option<String>)>,
+}
+
+fn controller_name(words: &[&str]) -> Option<String> {
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::from_descr(DEFAULT_BINDINGS).expect("Can't parse default bindings")
+    }
+
+    pub fn load<P>(path: P) -> Result<Bindings, String>
+        where P: AsRef<Path>
+    {
+        let mut descr = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut descr))
+            .map_err(|e| format!("Can't read bindings file: {}", e))?;
+        Bindings::from_descr(&descr)
+    }
+
+    pub fn from_descr(descr: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings {
+            keys: vec![],
+            buttons: vec![],
+            axes: vec![],
+            responses: vec![],
+        };
+        for line in descr.lines() {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            if words.len() < 2 || (words[0] != "SNAP" && words.len() < 3) {
+                return Err(format!("Can't parse binding '{}'", line));
+            }
+            match words[0] {
+                "KEY" | "BUTTON" => {
+                    let action = reverse_lookup(&ACTION_NAMES, words[1])
+                        .ok_or_else(|| format!("Unknown action '{}'", words[1]))?;
+                    for name in &words[2..] {
+                        if words[0] == "KEY" {
+                            bindings.keys.push((action, name.to_string()));
+                        } else {
+                            let button = reverse_lookup(&BUTTON_NAMES, name)
+                                .ok_or_else(|| format!("Unknown button '{}'", name))?;
+                            bindings.buttons.push((action, button));
+                        }
+                    }
+                }
+                "AXIS" => {
+                    let action = reverse_lookup(&AXIS_ACTION_NAMES, words[1])
+                        .ok_or_else(|| format!("Unknown axis action '{}'", words[1]))?;
+                    let axis = reverse_lookup(&AXIS_NAMES, words[2])
+                        .ok_or_else(|| format!("Unknown axis '{}'", words[2]))?;
+                    let inverted = match words.get(3) {
+                        None => false,
+                        Some(&"INVERT") => true,
+                        Some(word) => return Err(format!("Can't parse axis flag '{}'", word)),
+                    };
+                    bindings
+                        .axes
+                        .push(AxisBinding {
+                                  action: action,
+                                  axis: axis,
+                                  inverted: inverted,
+                              });
+                }
+                "RESPONSE" => {
+                    if words.len() < 5 {
+                        return Err(format!("Can't parse response '{}'", line));
+                    }
+                    let target = reverse_lookup(&RESPONSE_TARGET_NAMES, words[1])
+                        .ok_or_else(|| format!("Unknown response target '{}'", words[1]))?;
+                    let deadzone_kind = reverse_lookup(&DEADZONE_KIND_NAMES, words[2])
+                        .ok_or_else(|| format!("Unknown deadzone kind '{}'", words[2]))?;
+                    let value = |word: &str| {
+                        AxisValue::from_str(word)
+                            .map_err(|_| format!("Can't parse response value '{}'", word))
+                    };
+                    let response = AxisResponse {
+                        deadzone_kind: deadzone_kind,
+                        deadzone: value(words[3])?,
+                        exponent: value(words[4])?,
+                    };
+                    if response.deadzone < 0.0 || response.deadzone >= 1.0 ||
+                       response.exponent <= 0.0 {
+                        return Err(format!("Response values out of range '{}'", line));
+                    }
+                    bindings
+                        .responses
+                        .push((ResponseRule::Axis(target, response), controller_name(&words[5..])));
+                }
+                "SNAP" => {
+                    let snap = match words[1] {
+                        "ON" => true,
+                        "OFF" => false,
+                        _ => return Err(format!("Can't parse snap flag '{}'", words[1])),
+                    };
+                    bindings
+                        .responses
+                        .push((ResponseRule::Snap(snap), controller_name(&words[2..])));
+                }
+                _ => return Err(format!("Unknown binding type '{}'", words[0])),
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<(), String>
+        where P: AsRef<Path>
+    {
+        let file = File::create(path).map_err(|e| format!("Can't create bindings file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        let mut write = || -> ::std::io::Result<()> {
+            for &(action, name) in ACTION_NAMES.iter() {
+                let keys = self.keys
+                    .iter()
+                    .filter(|&&(a, _)| a == action)
+                    .map(|&(_, ref key)| key.as_str())
+                    .collect::<Vec<_>>();
+                if !keys.is_empty() {
+                    writeln!(writer, "KEY {} {}", name, keys.join(" "))?;
+                }
+            }
+            for &(action, name) in ACTION_NAMES.iter() {
+                let buttons = self.buttons
+                    .iter()
+                    .filter(|&&(a, _)| a == action)
+                    .filter_map(|&(_, button)| button_name(button))
+                    .collect::<Vec<_>>();
+                if !buttons.is_empty() {
+                    writeln!(writer, "BUTTON {} {}", name, buttons.join(" "))?;
+                }
+            }
+            for binding in &self.axes {
+                writeln!(writer,
+                         "AXIS {} {}{}",
+                         lookup(&AXIS_ACTION_NAMES, &binding.action).unwrap_or("?"),
+                         lookup(&AXIS_NAMES, &binding.axis).unwrap_or("?"),
+                         if binding.inverted { " INVERT" } else { "" })?;
+            }
+            for &(rule, ref controller) in &self.responses {
+                let controller = controller
+                    .as_ref()
+                    .map_or_else(String::new, |name| format!(" {}", name));
+                match rule {
+                    ResponseRule::Axis(target, response) => {
+                        writeln!(writer,
+                                 "RESPONSE {} {} {} {}{}",
+                                 lookup(&RESPONSE_TARGET_NAMES, &target).unwrap_or("?"),
+                                 lookup(&DEADZONE_KIND_NAMES, &response.deadzone_kind)
+                                     .unwrap_or("?"),
+                                 response.deadzone,
+                                 response.exponent,
+                                 controller)?
+                    }
+                    ResponseRule::Snap(snap) => {
+                        writeln!(writer,
+                                 "SNAP {}{}",
+                                 if snap { "ON" } else { "OFF" },
+                                 controller)?
+                    }
+                }
+            }
+            writer.flush()
+        };
+        write().map_err(|e| format!("Can't write bindings file: {}", e))
+    }
+
+    pub fn key_actions(&self, key: &str) -> Vec<Action> {
+        self.keys
+            .iter()
+            .filter(|&&(_, ref k)| k == key)
+            .map(|&(action, _)| action)
+            .collect()
+    }
+
+    pub fn buttons(&self) -> &[(Action, Button)] {
+        &self.buttons
+    }
+
+    pub fn axes(&self) -> &[AxisBinding] {
+        &self.axes
+    }
+
+    // Rules apply in file order, named ones only to gamepads whose name contains the name.
+    pub fn response(&self, controller: &str) -> ControllerResponse {
+        let mut response = ControllerResponse::default();
+        for &(rule, ref name) in &self.responses {
+            if name.as_ref().map_or(false, |name| !controller.contains(name.as_str())) {
+                continue;
+            }
+            match rule {
+                ResponseRule::Axis(ResponseTarget::Movement, axis) => response.movement = axis,
+                ResponseRule::Axis(ResponseTarget::Tilt, axis) => response.tilt = axis,
+                ResponseRule::Snap(snap) => response.snap_to_8_way = snap,
+            }
+        }
+        response
+    }
+
+    // The new key replaces the action's primary binding, alternates are kept. Keys of actions that
+    // can't be rebound are refused so that e.g. the rebinding key can't be taken away.
+    pub fn rebind_key(&mut self, action: Action, key: &str) -> bool {
+        if self.keys.iter().any(|&(a, ref k)| k == key && !is_rebindable(a)) {
+            return false;
+        }
+        self.keys.retain(|&(_, ref k)| k != key);
+        match self.keys.iter().position(|&(a, _)| a == action) {
+            Some(i) => self.keys[i].1 = key.to_string(),
+            None => self.keys.push((action, key.to_string())),
+        }
+        true
+    }
+
+    pub fn rebind_button(&mut self, action: Action, button: Button) -> bool {
+        if self.buttons.iter().any(|&(a, b)| b == button && !is_rebindable(a)) {
+            return false;
+        }
+        self.buttons.retain(|&(_, b)| b != button);
+        match self.buttons.iter().position(|&(a, _)| a == action) {
+            Some(i) => self.buttons[i].1 = button,
+            None => self.buttons.push((action, button)),
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn keys(bindings: &Bindings, action: Action) -> Vec<&str> {
+        bindings
+            .keys
+            .iter()
+            .filter(|&&(a, _)| a == action)
+            .map(|&(_, ref key)| key.as_str())
+            .collect()
+    }
+
+    fn buttons(bindings: &Bindings, action: Action) -> Vec<Button> {
+        bindings
+            .buttons()
+            .iter()
+            .filter(|&&(a, _)| a == action)
+            .map(|&(_, button)| button)
+            .collect()
+    }
+
+    #[test]
+    fn parses_default_bindings() {
+        let bindings = Bindings::new();
+        assert_eq!(bindings.key_actions("F1"), vec![Action::Rebind]);
+        assert_eq!(bindings.key_actions("Escape"), vec![Action::Exit]);
+        assert_eq!(keys(&bindings, Action::Fire), vec!["Space", "Z"]);
+        assert_eq!(buttons(&bindings, Action::Focus),
+                   vec![Button::West, Button::RightTrigger]);
+        let move_y = bindings
+            .axes()
+            .iter()
+            .find(|binding| binding.action == AxisAction::MoveY)
+            .expect("Can't find MOVE_Y binding");
+        assert_eq!(move_y.axis, Axis::LeftStickY);
+        assert!(move_y.inverted);
+        let response = bindings.response("Any pad");
+        assert_eq!(response.movement.deadzone_kind, DeadzoneKind::Radial);
+        assert_eq!(response.movement.deadzone, 0.15);
+        assert_eq!(response.tilt.deadzone_kind, DeadzoneKind::Axial);
+        assert!(!response.snap_to_8_way);
+    }
+
+    #[test]
+    fn rejects_invalid_bindings() {
+        for descr in &["KEY FIRE",
+                       "KEY SHOOT Space",
+                       "BUTTON FIRE Trigger",
+                       "AXIS MOVE_X LeftStickW",
+                       "AXIS MOVE_X LeftStickX FLIP",
+                       "AXIS TURN LeftStickX",
+                       "RESPONSE MOVE RADIAL 0.1",
+                       "RESPONSE SPIN RADIAL 0.1 1.0",
+                       "RESPONSE MOVE ROUND 0.1 1.0",
+                       "RESPONSE MOVE RADIAL 1.0 1.0",
+                       "RESPONSE MOVE RADIAL 0.1 0.0",
+                       "RESPONSE MOVE RADIAL 0.1 x",
+                       "SNAP MAYBE",
+                       "SNAP",
+                       "MACRO FIRE Space"] {
+            assert!(Bindings::from_descr(descr).is_err(), "accepted '{}'", descr);
+        }
+        assert!(Bindings::from_descr("# KEY SHOOT Space\n\n").is_ok());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut bindings = Bindings::new();
+        bindings.rebind_key(Action::Bomb, "C");
+        bindings.rebind_button(Action::Bomb, Button::North);
+        let descr = "RESPONSE MOVE AXIAL 0.25 2 Xbox 360\nSNAP ON Xbox 360";
+        let extra = Bindings::from_descr(descr).expect("Can't parse bindings");
+        bindings.responses.extend(extra.responses);
+
+        let path = env::temp_dir().join("yavss_bindings_round_trip.txt");
+        bindings.save(&path).expect("Can't save bindings");
+        let loaded = Bindings::load(&path).expect("Can't load bindings");
+        for &(action, _) in ACTION_NAMES.iter() {
+            assert_eq!(keys(&loaded, action), keys(&bindings, action));
+            assert_eq!(buttons(&loaded, action), buttons(&bindings, action));
+        }
+        assert_eq!(loaded.axes().len(), bindings.axes().len());
+        for (loaded, binding) in loaded.axes().iter().zip(bindings.axes()) {
+            assert_eq!(loaded.action, binding.action);
+            assert_eq!(loaded.axis, binding.axis);
+            assert_eq!(loaded.inverted, binding.inverted);
+        }
+        for controller in &["Generic pad", "Xbox 360 Controller"] {
+            assert_eq!(loaded.response(controller), bindings.response(controller));
+        }
+    }
+
+    #[test]
+    fn response_matches_controller_names() {
+        let descr = "RESPONSE MOVE RADIAL 0.1 1.0\n\
+                     RESPONSE MOVE AXIAL 0.2 2.0 Xbox\n\
+                     SNAP ON Xbox\n\
+                     RESPONSE TILT AXIAL 0.3 1.0\n\
+                     RESPONSE TILT RADIAL 0.4 1.0";
+        let bindings = Bindings::from_descr(descr).expect("Can't parse bindings");
+
+        let generic = bindings.response("Generic pad");
+        assert_eq!(generic.movement.deadzone_kind, DeadzoneKind::Radial);
+        assert_eq!(generic.movement.deadzone, 0.1);
+        assert!(!generic.snap_to_8_way);
+        assert_eq!(generic.tilt.deadzone, 0.4);
+
+        let xbox = bindings.response("Xbox 360 Controller");
+        assert_eq!(xbox.movement.deadzone_kind, DeadzoneKind::Axial);
+        assert_eq!(xbox.movement.deadzone, 0.2);
+        assert_eq!(xbox.movement.exponent, 2.0);
+        assert!(xbox.snap_to_8_way);
+        assert_eq!(xbox.tilt, generic.tilt);
+
+        assert_eq!(bindings.response("xbox"), generic);
+        assert_eq!(Bindings::from_descr("").map(|b| b.response("Xbox")),
+                   Ok(ControllerResponse::default()));
+    }
+
+    #[test]
+    fn rebind_replaces_primary_binding() {
+        let mut bindings = Bindings::new();
+        assert!(bindings.rebind_key(Action::Fire, "C"));
+        assert_eq!(keys(&bindings, Action::Fire), vec!["C", "Z"]);
+        assert!(bindings.key_actions("Space").is_empty());
+
+        assert!(bindings.rebind_key(Action::Bomb, "Z"));
+        assert_eq!(keys(&bindings, Action::Fire), vec!["C"]);
+        assert_eq!(keys(&bindings, Action::Bomb), vec!["Z"]);
+
+        assert!(bindings.rebind_button(Action::Fire, Button::East));
+        assert_eq!(buttons(&bindings, Action::Fire), vec![Button::East]);
+        assert!(buttons(&bindings, Action::Bomb).is_empty());
+        assert!(bindings.rebind_button(Action::Bomb, Button::North));
+        assert_eq!(buttons(&bindings, Action::Bomb), vec![Button::North]);
+    }
+
+    #[test]
+    fn rebind_refuses_system_bindings() {
+        let mut bindings = Bindings::new();
+        for &key in &["F1", "F5", "F9", "Escape"] {
+            let actions = bindings.key_actions(key);
+            assert!(!bindings.rebind_key(Action::Fire, key));
+            assert_eq!(bindings.key_actions(key), actions);
+        }
+        assert_eq!(keys(&bindings, Action::Fire), vec!["Space", "Z"]);
+
+        let mut bindings = Bindings::from_descr("BUTTON FIRE South\nBUTTON EXIT Select")
+            .expect("Can't parse bindings");
+        assert!(!bindings.rebind_button(Action::Fire, Button::Select));
+        assert_eq!(buttons(&bindings, Action::Exit), vec![Button::Select]);
+        assert_eq!(buttons(&bindings, Action::Fire), vec![Button::South]);
+    }
+}
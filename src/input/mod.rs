@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+pub mod bindings;
+mod poller;
+pub mod response;
+mod scripted;
+
+pub use self::bindings::Bindings;
+pub use self::poller::{GamepadSelection, InputPoller};
+pub use self::scripted::ScriptedInput;
+
+pub type AxisValue = f32;
+
+pub trait Controls {
+    fn x_move(&self) -> AxisValue;
+    fn y_move(&self) -> AxisValue;
+    fn left_tilt(&self) -> AxisValue;
+    fn right_tilt(&self) -> AxisValue;
+    fn fire_is_pressed(&self) -> bool;
+    fn focus_is_pressed(&self) -> bool;
+    fn bomb_is_pressed(&self) -> bool;
+    fn pause_is_pressed(&self) -> bool;
+}
+
+pub trait InputSource {
+    fn poll_events(&mut self);
+    fn exit(&self) -> bool;
+    fn player_input(&self, player: usize) -> InputState;
+
+    fn players(&self) -> usize {
+        1
+    }
+}
+
+pub fn player_inputs<I>(input: &I, players: usize) -> Vec<InputState>
+    where I: InputSource + ?Sized
+{
+    (0..players).map(|player| input.player_input(player)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputState {
+    x_move: AxisValue,
+    y_move: AxisValue,
+    left_tilt: AxisValue,
+    right_tilt: AxisValue,
+    fire_is_pressed: bool,
+    focus_is_pressed: bool,
+    bomb_is_pressed: bool,
+    pause_is_pressed: bool,
+}
+
+impl Default for InputState {
+    fn default() -> InputState {
+        InputState {
+            x_move: 0.0,
+            y_move: 0.0,
+            left_tilt: 0.0,
+            right_tilt: 0.0,
+            fire_is_pressed: false,
+            focus_is_pressed: false,
+            bomb_is_pressed: false,
+            pause_is_pressed: false,
+        }
+    }
+}
+
+impl InputState {
+    pub fn new(x_move: AxisValue,
+               y_move: AxisValue,
+               fire_is_pressed: bool,
+               focus_is_pressed: bool,
+               bomb_is_pressed: bool)
+               -> InputState {
+        InputState {
+            x_move: x_move,
+            y_move: y_move,
+            fire_is_pressed: fire_is_pressed,
+            focus_is_pressed: focus_is_pressed,
+            bomb_is_pressed: bomb_is_pressed,
+            ..Default::default()
+        }
+    }
+}
+
+impl Controls for InputState {
+    fn x_move(&self) -> AxisValue {
+        self.x_move
+    }
+
+    fn y_move(&self) -> AxisValue {
+        self.y_move
+    }
+
+    fn left_tilt(&self) -> AxisValue {
+        self.left_tilt
+    }
+
+    fn right_tilt(&self) -> AxisValue {
+        self.right_tilt
+    }
+
+    fn fire_is_pressed(&self) -> bool {
+        self.fire_is_pressed
+    }
+
+    fn focus_is_pressed(&self) -> bool {
+        self.focus_is_pressed
+    }
+
+    fn bomb_is_pressed(&self) -> bool {
+        self.bomb_is_pressed
+    }
+
+    fn pause_is_pressed(&self) -> bool {
+        self.pause_is_pressed
+    }
+}
+
+impl fmt::Display for InputState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} {} {} {} {} {} {}",
+               self.x_move,
+               self.y_move,
+               self.left_tilt,
+               self.right_tilt,
+               self.fire_is_pressed as u8,
+               self.focus_is_pressed as u8,
+               self.bomb_is_pressed as u8)
+    }
+}
+
+impl FromStr for InputState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputState, String> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        if words.len() != 7 {
+            return Err(format!("Can't parse input state '{}'", s));
+        }
+        let axis = |word: &str| {
+            AxisValue::from_str(word).map_err(|_| format!("Can't parse axis value '{}'", word))
+        };
+        let button = |word: &str| match word {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(format!("Can't parse button state '{}'", word)),
+        };
+        Ok(InputState {
+               x_move: axis(words[0])?,
+               y_move: axis(words[1])?,
+               left_tilt: axis(words[2])?,
+               right_tilt: axis(words[3])?,
+               fire_is_pressed: button(words[4])?,
+               focus_is_pressed: button(words[5])?,
+               bomb_is_pressed: button(words[6])?,
+               pause_is_pressed: false,
+           })
+    }
+}
@@ -0,0 +1,412 @@
+use glium::backend::glutin_backend::WinRef;
+use gilrs::Gilrs;
+use input::{InputSource, InputState};
+use input::bindings::{self, Action, AxisAction, Bindings, REBINDABLE_ACTIONS};
+use input::response::{self, ControllerResponse};
+
+#[derive(Debug, Default)]
+struct KeyboardState {
+    left_is_pressed: bool,
+    right_is_pressed: bool,
+    down_is_pressed: bool,
+    up_is_pressed: bool,
+    fire_is_pressed: bool,
+    focus_is_pressed: bool,
+    bomb_is_pressed: bool,
+    pause_is_pressed: bool,
+}
+
+impl KeyboardState {
+    fn set(&mut self, action: Action, is_pressed: bool) {
+        match action {
+            Action::Up => self.up_is_pressed = is_pressed,
+            Action::Down => self.down_is_pressed = is_pressed,
+            Action::Left => self.left_is_pressed = is_pressed,
+            Action::Right => self.right_is_pressed = is_pressed,
+            Action::Fire => self.fire_is_pressed = is_pressed,
+            Action::Focus => self.focus_is_pressed = is_pressed,
+            Action::Bomb => self.bomb_is_pressed = is_pressed,
+            Action::Pause => self.pause_is_pressed = is_pressed,
+            Action::Exit | Action::Rebind | Action::SaveState | Action::LoadState => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadSelection {
+    Auto,
+    Fixed(usize),
+    Disabled,
+}
+
+const KEYBOARD_PLAYER: usize = 0;
+
+struct PlayerInput {
+    state: InputState,
+    gamepad: Option<usize>,
+    gamepad_selection: GamepadSelection,
+    response: ControllerResponse,
+}
+
+impl PlayerInput {
+    fn new() -> PlayerInput {
+        PlayerInput {
+            state: Default::default(),
+            gamepad: None,
+            gamepad_selection: GamepadSelection::Auto,
+            response: Default::default(),
+        }
+    }
+}
+
+struct Rebinding {
+    action_index: usize,
+    bindings: Bindings,
+}
+
+pub struct InputPoller<'a> {
+    players: Vec<PlayerInput>,
+    keyboard: KeyboardState,
+    exit: bool,
+    bindings: Bindings,
+    rebinding: Option<Rebinding>,
+    bindings_changed: bool,
+    save_state_requested: bool,
+    load_state_requested: bool,
+    gilrs: Gilrs,
+    win: WinRef<'a>,
+}
+
+impl<'a> InputPoller<'a> {
+    pub fn new(win: WinRef<'a>, bindings: Bindings) -> InputPoller<'a> {
+        let mut poller = InputPoller {
+            players: vec![PlayerInput::new()],
+            keyboard: Default::default(),
+            exit: false,
+            bindings: bindings,
+            rebinding: None,
+            bindings_changed: false,
+            save_state_requested: false,
+            load_state_requested: false,
+            gilrs: Gilrs::new(),
+            win: win,
+        };
+        poller.update_gamepads();
+        poller
+    }
+
+    pub fn set_players(&mut self, players: usize) {
+        while self.players.len() < players {
+            self.players.push(PlayerInput::new());
+        }
+        self.players.truncate(players.max(1));
+        for player in &mut self.players {
+            player.gamepad = None;
+        }
+        self.update_gamepads();
+    }
+
+    pub fn gamepad(&self, player: usize) -> Option<usize> {
+        self.players.get(player).and_then(|player| player.gamepad)
+    }
+
+    pub fn select_gamepad(&mut self, player: usize, selection: GamepadSelection) {
+        if let Some(player) = self.players.get_mut(player) {
+            player.gamepad_selection = selection;
+            player.gamepad = None;
+        }
+        self.update_gamepads();
+    }
+
+    // Fixed selections are served first, then players on Auto keep their connected gamepad and
+    // the rest get the free ones in order, so one gamepad never drives two players. The keyboard
+    // player is served last, so other players get a device of their own first.
+    fn update_gamepads(&mut self) {
+        let connected = self.gilrs
+            .gamepads()
+            .filter(|&(_, gamepad)| gamepad.is_connected())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let mut gamepads = self.players
+            .iter()
+            .map(|player| match player.gamepad_selection {
+                     GamepadSelection::Fixed(id) if connected.contains(&id) => Some(id),
+                     GamepadSelection::Auto => {
+                         player.gamepad.and_then(|id| if connected.contains(&id) {
+                                                     Some(id)
+                                                 } else {
+                                                     None
+                                                 })
+                     }
+                     _ => None,
+                 })
+            .collect::<Vec<_>>();
+        for i in 0..gamepads.len() {
+            if gamepads[..i].iter().any(|&gamepad| gamepad.is_some() && gamepad == gamepads[i]) {
+                gamepads[i] = None;
+            }
+        }
+        let mut order = (0..self.players.len())
+            .filter(|&i| i != KEYBOARD_PLAYER)
+            .collect::<Vec<_>>();
+        order.push(KEYBOARD_PLAYER);
+        for i in order {
+            let selection = self.players[i].gamepad_selection;
+            if gamepads[i].is_some() || selection != GamepadSelection::Auto {
+                continue;
+            }
+            gamepads[i] = connected
+                .iter()
+                .cloned()
+                .find(|&id| !gamepads.contains(&Some(id)));
+        }
+
+        for (player, gamepad) in self.players.iter_mut().zip(gamepads) {
+            player.gamepad = gamepad;
+        }
+        self.update_responses();
+    }
+
+    fn update_responses(&mut self) {
+        for player in &mut self.players {
+            player.response = match player.gamepad {
+                Some(id) => self.bindings.response(self.gilrs[id].name()),
+                None => Default::default(),
+            };
+        }
+    }
+
+    fn update_player_state(&mut self, player: usize) {
+        let player_index = player;
+        let player = &mut self.players[player];
+        let state = &mut player.state;
+        if player_index == KEYBOARD_PLAYER {
+            if self.keyboard.right_is_pressed {
+                state.x_move += 0.5;
+            }
+            if self.keyboard.left_is_pressed {
+                state.x_move -= 0.5;
+            }
+            if self.keyboard.up_is_pressed {
+                state.y_move -= 0.5;
+            }
+            if self.keyboard.down_is_pressed {
+                state.y_move += 0.5;
+            }
+            state.fire_is_pressed = self.keyboard.fire_is_pressed;
+            state.focus_is_pressed = self.keyboard.focus_is_pressed;
+            state.bomb_is_pressed = self.keyboard.bomb_is_pressed;
+            state.pause_is_pressed = self.keyboard.pause_is_pressed;
+        }
+
+        if let Some(id) = player.gamepad {
+            let gamepad = &self.gilrs[id];
+            let (mut x_move, mut y_move) = (0.0, 0.0);
+            let (mut left_tilt, mut right_tilt) = (0.0, 0.0);
+            for binding in self.bindings.axes() {
+                let value = gamepad.value(binding.axis);
+                let value = if binding.inverted { -value } else { value };
+                match binding.action {
+                    AxisAction::MoveX => x_move += value,
+                    AxisAction::MoveY => y_move += value,
+                    AxisAction::LeftTilt => left_tilt += value,
+                    AxisAction::RightTilt => right_tilt += value,
+                }
+            }
+            let (mut x_move, mut y_move) = player.response.movement.apply_stick(x_move, y_move);
+            for &(action, button) in self.bindings.buttons() {
+                if !gamepad.is_pressed(button) {
+                    continue;
+                }
+                match action {
+                    Action::Up => y_move -= 1.0,
+                    Action::Down => y_move += 1.0,
+                    Action::Left => x_move -= 1.0,
+                    Action::Right => x_move += 1.0,
+                    Action::Fire => state.fire_is_pressed = true,
+                    Action::Focus => state.focus_is_pressed = true,
+                    Action::Bomb => state.bomb_is_pressed = true,
+                    Action::Pause => state.pause_is_pressed = true,
+                    Action::Exit | Action::Rebind | Action::SaveState | Action::LoadState => {}
+                }
+            }
+            if player.response.snap_to_8_way {
+                let (x, y) = response::snap_to_8_way(x_move, y_move);
+                x_move = x;
+                y_move = y;
+            }
+            state.x_move += x_move;
+            state.y_move += y_move;
+            state.left_tilt = player.response.tilt.apply_axis(left_tilt);
+            state.right_tilt = player.response.tilt.apply_axis(right_tilt);
+        }
+        state.x_move = state.x_move.max(-1.0).min(1.0);
+        state.y_move = state.y_move.max(-1.0).min(1.0);
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn is_rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+
+    pub fn rebinding_action(&self) -> Option<Action> {
+        self.rebinding
+            .as_ref()
+            .map(|rebinding| REBINDABLE_ACTIONS[rebinding.action_index])
+    }
+
+    pub fn start_rebinding(&mut self) {
+        self.rebinding = Some(Rebinding {
+                                  action_index: 0,
+                                  bindings: self.bindings.clone(),
+                              });
+        self.keyboard = Default::default();
+    }
+
+    pub fn take_bindings_changed(&mut self) -> bool {
+        let changed = self.bindings_changed;
+        self.bindings_changed = false;
+        changed
+    }
+
+    pub fn take_save_state_request(&mut self) -> bool {
+        let requested = self.save_state_requested;
+        self.save_state_requested = false;
+        requested
+    }
+
+    pub fn take_load_state_request(&mut self) -> bool {
+        let requested = self.load_state_requested;
+        self.load_state_requested = false;
+        requested
+    }
+
+    fn next_rebinding_action(&mut self) {
+        let finished = match self.rebinding {
+            Some(ref mut rebinding) => {
+                rebinding.action_index += 1;
+                rebinding.action_index >= REBINDABLE_ACTIONS.len()
+            }
+            None => false,
+        };
+        if finished {
+            let rebinding = self.rebinding.take().expect("Can't finish rebinding");
+            self.bindings = rebinding.bindings;
+            self.bindings_changed = true;
+            self.update_responses();
+        }
+    }
+
+    fn rebind_key(&mut self, key: &str) {
+        if self.bindings.key_actions(key).contains(&Action::Exit) {
+            self.rebinding = None;
+            return;
+        }
+        if let Some(action) = self.rebinding_action() {
+            let rebound = match self.rebinding {
+                Some(ref mut rebinding) => rebinding.bindings.rebind_key(action, key),
+                None => false,
+            };
+            if rebound {
+                self.next_rebinding_action();
+            }
+        }
+    }
+}
+
+impl<'a> InputSource for InputPoller<'a> {
+    fn poll_events(&mut self) {
+        use glium::glutin;
+        use glium::glutin::Event;
+        use gilrs;
+
+        let events = self.win.poll_events().collect::<Vec<_>>();
+        for event in events {
+            match event {
+                Event::KeyboardInput(element_state, _, Some(key_code)) => {
+                    let is_pressed = element_state == glutin::ElementState::Pressed;
+                    let key = format!("{:?}", key_code);
+                    if self.is_rebinding() {
+                        if is_pressed {
+                            self.rebind_key(&key);
+                        }
+                        continue;
+                    }
+                    for action in self.bindings.key_actions(&key) {
+                        match action {
+                            Action::Exit => self.exit = self.exit || is_pressed,
+                            Action::Rebind if is_pressed => self.start_rebinding(),
+                            Action::SaveState if is_pressed => self.save_state_requested = true,
+                            Action::LoadState if is_pressed => self.load_state_requested = true,
+                            _ => self.keyboard.set(action, is_pressed),
+                        }
+                    }
+                }
+                Event::Closed => {
+                    self.exit = true;
+                }
+                _ => {}
+            }
+        }
+
+        let gamepads = self.players
+            .iter()
+            .filter_map(|player| player.gamepad)
+            .collect::<Vec<_>>();
+        let mut buttons = vec![];
+        let mut gamepads_changed = false;
+        for (id, event) in self.gilrs.poll_events() {
+            match event {
+                gilrs::Event::ButtonPressed(button, _) if gamepads.contains(&id) => {
+                    buttons.push(button);
+                }
+                gilrs::Event::Connected |
+                gilrs::Event::Disconnected => gamepads_changed = true,
+                _ => {}
+            }
+        }
+        if gamepads_changed {
+            self.update_gamepads();
+        }
+        if self.is_rebinding() {
+            for button in buttons {
+                if bindings::button_name(button).is_none() {
+                    continue;
+                }
+                if let Some(action) = self.rebinding_action() {
+                    let rebound = match self.rebinding {
+                        Some(ref mut rebinding) => rebinding.bindings.rebind_button(action, button),
+                        None => false,
+                    };
+                    if rebound {
+                        self.next_rebinding_action();
+                    }
+                }
+            }
+        }
+
+        for player in 0..self.players.len() {
+            self.players[player].state = Default::default();
+            if !self.is_rebinding() {
+                self.update_player_state(player);
+            }
+        }
+    }
+
+    fn exit(&self) -> bool {
+        self.exit
+    }
+
+    fn players(&self) -> usize {
+        self.players.len()
+    }
+
+    fn player_input(&self, player: usize) -> InputState {
+        self.players
+            .get(player)
+            .map_or_else(Default::default, |player| player.state)
+    }
+}
@@ -0,0 +1,199 @@
+use input::AxisValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadzoneKind {
+    Radial,
+    Axial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisResponse {
+    pub deadzone_kind: DeadzoneKind,
+    pub deadzone: AxisValue,
+    pub exponent: AxisValue,
+}
+
+impl Default for AxisResponse {
+    fn default() -> AxisResponse {
+        AxisResponse {
+            deadzone_kind: DeadzoneKind::Radial,
+            deadzone: 0.0,
+            exponent: 1.0,
+        }
+    }
+}
+
+impl AxisResponse {
+    fn scale(&self, magnitude: AxisValue) -> AxisValue {
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+        ((magnitude - self.deadzone) / (1.0 - self.deadzone))
+            .min(1.0)
+            .powf(self.exponent)
+    }
+
+    pub fn apply_axis(&self, value: AxisValue) -> AxisValue {
+        self.scale(value.abs()) * value.signum()
+    }
+
+    pub fn apply_stick(&self, x: AxisValue, y: AxisValue) -> (AxisValue, AxisValue) {
+        match self.deadzone_kind {
+            DeadzoneKind::Radial => {
+                let magnitude = x.hypot(y);
+                if magnitude == 0.0 {
+                    return (0.0, 0.0);
+                }
+                let scale = self.scale(magnitude) / magnitude;
+                (x * scale, y * scale)
+            }
+            DeadzoneKind::Axial => (self.apply_axis(x), self.apply_axis(y)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControllerResponse {
+    pub movement: AxisResponse,
+    pub tilt: AxisResponse,
+    pub snap_to_8_way: bool,
+}
+
+impl Default for ControllerResponse {
+    fn default() -> ControllerResponse {
+        ControllerResponse {
+            movement: Default::default(),
+            tilt: Default::default(),
+            snap_to_8_way: false,
+        }
+    }
+}
+
+pub fn snap_to_8_way(x: AxisValue, y: AxisValue) -> (AxisValue, AxisValue) {
+    use std::f32::consts::FRAC_PI_4;
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let angle = (y.atan2(x) / FRAC_PI_4).round() * FRAC_PI_4;
+    (angle.cos().round(), angle.sin().round())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(deadzone_kind: DeadzoneKind,
+                deadzone: AxisValue,
+                exponent: AxisValue)
+                -> AxisResponse {
+        AxisResponse {
+            deadzone_kind: deadzone_kind,
+            deadzone: deadzone,
+            exponent: exponent,
+        }
+    }
+
+    fn assert_close(value: (AxisValue, AxisValue), expected: (AxisValue, AxisValue)) {
+        assert!((value.0 - expected.0).abs() < 1e-5 && (value.1 - expected.1).abs() < 1e-5,
+                "{:?} != {:?}",
+                value,
+                expected);
+    }
+
+    #[test]
+    fn scales_magnitude() {
+        let cases = [(0.2, 1.0, 0.0, 0.0),
+                     (0.2, 1.0, 0.2, 0.0),
+                     (0.2, 1.0, 0.201, 0.00125),
+                     (0.2, 1.0, 0.6, 0.5),
+                     (0.2, 1.0, 1.0, 1.0),
+                     (0.2, 1.0, 1.5, 1.0),
+                     (0.2, 2.0, 0.6, 0.25),
+                     (0.2, 2.0, 1.0, 1.0),
+                     (0.0, 0.5, 0.25, 0.5),
+                     (0.0, 1.0, 0.3, 0.3)];
+        for &(deadzone, exponent, magnitude, expected) in &cases {
+            let value = response(DeadzoneKind::Radial, deadzone, exponent).scale(magnitude);
+            assert!((value - expected).abs() < 1e-5,
+                    "deadzone {} exponent {} magnitude {}: {} != {}",
+                    deadzone,
+                    exponent,
+                    magnitude,
+                    value,
+                    expected);
+        }
+    }
+
+    #[test]
+    fn applies_axis_response_with_sign() {
+        let axis = response(DeadzoneKind::Axial, 0.2, 1.0);
+        assert_eq!(axis.apply_axis(0.1), 0.0);
+        assert_eq!(axis.apply_axis(-0.2), 0.0);
+        assert!((axis.apply_axis(0.6) - 0.5).abs() < 1e-5);
+        assert!((axis.apply_axis(-0.6) + 0.5).abs() < 1e-5);
+        assert_eq!(axis.apply_axis(-1.0), -1.0);
+    }
+
+    #[test]
+    fn applies_radial_stick_response() {
+        let radial = response(DeadzoneKind::Radial, 0.2, 1.0);
+        let cases = [((0.0, 0.0), (0.0, 0.0)),
+                     ((0.1, 0.1), (0.0, 0.0)),
+                     ((0.14, 0.14), (0.0, 0.0)),
+                     ((0.15, 0.15), (0.010723, 0.010723)),
+                     ((0.6, 0.8), (0.6, 0.8)),
+                     ((-0.3, 0.4), (-0.225, 0.3)),
+                     ((0.0, -0.6), (0.0, -0.5)),
+                     ((1.0, 1.0), (0.707107, 0.707107))];
+        for &(stick, expected) in &cases {
+            assert_close(radial.apply_stick(stick.0, stick.1), expected);
+        }
+
+        let curved = response(DeadzoneKind::Radial, 0.1, 2.0);
+        for &(x, y) in &[(0.3, 0.4), (-0.5, 0.2), (0.05, -0.9)] {
+            let (scaled_x, scaled_y) = curved.apply_stick(x, y);
+            assert!((scaled_y.atan2(scaled_x) - y.atan2(x)).abs() < 1e-5);
+            let magnitude: AxisValue = x.hypot(y);
+            let expected = ((magnitude - 0.1) / 0.9).powf(2.0);
+            assert!((scaled_x.hypot(scaled_y) - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn applies_axial_stick_response() {
+        let axial = response(DeadzoneKind::Axial, 0.2, 1.0);
+        let cases = [((0.0, 0.0), (0.0, 0.0)),
+                     ((0.15, 0.15), (0.0, 0.0)),
+                     ((0.1, 0.9), (0.0, 0.875)),
+                     ((-0.6, 0.19), (-0.5, 0.0)),
+                     ((1.0, -1.0), (1.0, -1.0))];
+        for &(stick, expected) in &cases {
+            assert_close(axial.apply_stick(stick.0, stick.1), expected);
+        }
+    }
+
+    #[test]
+    fn snaps_to_8_way() {
+        let at = |degrees: AxisValue| {
+            let radians = degrees.to_radians();
+            snap_to_8_way(radians.cos(), radians.sin())
+        };
+        let cases = [(0.0, (1.0, 0.0)),
+                     (22.0, (1.0, 0.0)),
+                     (23.0, (1.0, 1.0)),
+                     (67.0, (1.0, 1.0)),
+                     (68.0, (0.0, 1.0)),
+                     (112.0, (0.0, 1.0)),
+                     (113.0, (-1.0, 1.0)),
+                     (180.0, (-1.0, 0.0)),
+                     (-22.0, (1.0, 0.0)),
+                     (-23.0, (1.0, -1.0)),
+                     (-157.0, (-1.0, -1.0)),
+                     (-158.0, (-1.0, 0.0))];
+        for &(degrees, expected) in &cases {
+            assert_close(at(degrees), expected);
+        }
+        assert_eq!(snap_to_8_way(0.0, 0.0), (0.0, 0.0));
+        assert_close(snap_to_8_way(0.3, 0.01), (1.0, 0.0));
+    }
+}
@@ -0,0 +1,54 @@
+use input::{InputSource, InputState};
+
+pub struct ScriptedInput<F>
+    where F: FnMut(usize) -> Option<InputState>
+{
+    script: F,
+    tick: usize,
+    state: InputState,
+    exit: bool,
+}
+
+impl<F> ScriptedInput<F>
+    where F: FnMut(usize) -> Option<InputState>
+{
+    pub fn new(script: F) -> ScriptedInput<F> {
+        ScriptedInput {
+            script: script,
+            tick: 0,
+            state: Default::default(),
+            exit: false,
+        }
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+}
+
+impl<F> InputSource for ScriptedInput<F>
+    where F: FnMut(usize) -> Option<InputState>
+{
+    fn poll_events(&mut self) {
+        match (self.script)(self.tick) {
+            Some(state) => self.state = state,
+            None => {
+                self.state = Default::default();
+                self.exit = true;
+            }
+        }
+        self.tick += 1;
+    }
+
+    fn exit(&self) -> bool {
+        self.exit
+    }
+
+    fn player_input(&self, player: usize) -> InputState {
+        if player == 0 {
+            self.state
+        } else {
+            Default::default()
+        }
+    }
+}
@@ -0,0 +1,21 @@
+extern crate gilrs;
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate image;
+extern crate xml;
+
+pub mod util;
+pub mod input;
+pub mod sprites;
+pub mod collision;
+pub mod bulletml;
+pub mod stage;
+pub mod weapon;
+pub mod snapshot;
+pub mod scene;
+pub mod replay;
+pub mod netplay;
+pub mod headless;
+pub mod game;
+pub mod display;
@@ -1,24 +1,21 @@
-extern crate gilrs;
-#[macro_use]
 extern crate glium;
-extern crate cgmath;
-extern crate image;
-
-mod util;
-mod input;
-mod sprites;
-mod collision;
-mod scene;
-mod display;
+extern crate yavss;
 
 use glium::glutin;
 use std::time::Duration;
+use yavss::{display, game, headless, input, netplay, replay, scene, sprites, util};
+use yavss::input::InputSource;
 
 const WINDOW_WIDTH: u32 = 800;
 const WINDOW_HEIGHT: u32 = 800;
 const VIRTUAL_WIDHT: u32 = 200;
 const VIRTUAL_HEIGHT: u32 = 200;
-const FRAME_RATE: u64 = 60;
+const TICKS_PER_SECOND: u32 = 60;
+const MAX_TICKS_PER_FRAME: u32 = 5;
+const BINDINGS_PATH: &'static str = "bindings.txt";
+const SAVE_STATE_PATH: &'static str = "savestate.bin";
+const HIGH_SCORE_PATH: &'static str = "highscore.txt";
+const FRAMES_TO_COUNT: usize = 60;
 
 fn create_window() -> glium::backend::glutin_backend::GlutinFacade {
     use glium::DisplayBuild;
@@ -32,50 +29,469 @@ fn create_window() -> glium::backend::glutin_backend::GlutinFacade {
         .expect("Can't create gluim window")
 }
 
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+fn time_seed() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() ^ duration.subsec_nanos() as u64)
+        .unwrap_or(scene::DEFAULT_SEED)
+}
+
+fn network_conditions(args: &[String]) -> Option<(Duration, Duration, f32)> {
+    let milliseconds = |name: &str| {
+        arg_value(args, name)
+            .map(|ms| Duration::from_millis(ms.parse().expect("Can't parse milliseconds")))
+    };
+    let latency = milliseconds("--latency");
+    let jitter = milliseconds("--jitter");
+    let loss = arg_value(args, "--loss")
+        .map(|loss| loss.parse::<f32>().expect("Can't parse packet loss") / 100.0);
+    if latency.is_none() && jitter.is_none() && loss.is_none() {
+        return None;
+    }
+    Some((latency.unwrap_or_default(), jitter.unwrap_or_default(), loss.unwrap_or(0.0)))
+}
+
+fn netplay_transport(args: &[String],
+                     transport: netplay::UdpTransport)
+                     -> Box<netplay::Transport> {
+    match network_conditions(args) {
+        Some((latency, jitter, loss)) => {
+            Box::new(netplay::LossyTransport::new(transport, latency, jitter, loss, time_seed()))
+        }
+        None => Box::new(transport),
+    }
+}
+
+fn load_saved_state(path: &str) -> Option<Vec<u8>> {
+    use std::io::Read;
+    let mut bytes = vec![];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .ok()
+        .map(|_| bytes)
+}
+
+fn save_state(path: &str, bytes: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    std::fs::File::create(path)
+        .and_then(|mut file| file.write_all(bytes))
+        .map_err(|e| format!("Can't save state: {}", e))
+}
+
+fn load_high_score(path: &str) -> u64 {
+    use std::io::Read;
+    let mut text = String::new();
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .ok()
+        .and_then(|_| text.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn save_high_score(path: &str, high_score: u64) -> Result<(), String> {
+    use std::io::Write;
+    std::fs::File::create(path)
+        .and_then(|mut file| writeln!(file, "{}", high_score))
+        .map_err(|e| format!("Can't save high score: {}", e))
+}
+
+// The first recorded run goes to the given path, later runs of the same session get their number
+// appended to it.
+fn save_recording(recording: &replay::Replay, path: &str, run: usize) {
+    let path = if run == 0 {
+        path.to_string()
+    } else {
+        format!("{}.{}", path, run + 1)
+    };
+    if let Err(err) = recording.save(path) {
+        eprintln!("{}", err);
+    }
+}
+
+fn best_score(scene: &scene::Scene) -> u64 {
+    scene.players().iter().map(|player| player.score()).max().unwrap_or(0)
+}
+
+fn print_run_stats(seed: u64, tick_duration: Duration, stats: &headless::RunStats) {
+    println!("seed: {}", seed);
+    println!("ticks: {} ({:.1} s simulated, {:.3} s real)",
+             stats.ticks,
+             util::seconds(tick_duration * stats.ticks as u32),
+             util::seconds(stats.real_duration));
+    for (i, player) in stats.scene.players().iter().enumerate() {
+        println!("player {}: {:?}, lives: {}, bombs: {}, weapon level: {}",
+                 i + 1,
+                 player.status(),
+                 player.lives(),
+                 player.bombs(),
+                 player.weapon_level());
+        println!("player {}: score: {}, grazes: {}, chain: {}",
+                 i + 1,
+                 player.score(),
+                 player.grazes(),
+                 player.chain());
+    }
+    println!("objects: {}", stats.scene.total_objects());
+}
+
+fn print_netplay_stats(seed: u64, stats: &headless::NetplayStats) {
+    println!("seed: {}, ticks: {}, frames: {}, real time: {:.3} s",
+             seed,
+             stats.ticks,
+             stats.frames,
+             util::seconds(stats.real_duration));
+    for (i, peer) in stats.peers.iter().enumerate() {
+        println!("peer {}: rollbacks: {}, resimulated ticks: {}, stalls: {}, synchronized: {}, \
+                  checksum: {:016x}",
+                 i + 1,
+                 peer.rollbacks,
+                 peer.resimulated_ticks,
+                 peer.stalls,
+                 peer.synchronized,
+                 peer.checksum);
+    }
+    println!("local checksum: {:016x}: {}",
+             stats.local_checksum,
+             if stats.in_sync() { "in sync" } else { "DESYNC" });
+}
+
 fn main() {
     use std::time::Instant;
 
+    let args = std::env::args().collect::<Vec<_>>();
+    let record_path = arg_value(&args, "--record");
+    let playback = arg_value(&args, "--replay")
+        .map(|path| replay::Replay::load(path).expect("Can't load replay"));
+    let fixed_seed = arg_value(&args, "--seed")
+        .map(|seed| seed.parse::<u64>().expect("Can't parse seed"));
+    let seed = match playback {
+        Some(ref playback) => playback.seed(),
+        None => fixed_seed.unwrap_or_else(time_seed),
+    };
+    let players = match playback {
+        Some(ref playback) => playback.players(),
+        None => {
+            arg_value(&args, "--players")
+                .map_or(1, |players| players.parse().expect("Can't parse players count"))
+        }
+    };
+    let input_delay = arg_value(&args, "--input-delay")
+        .map_or(netplay::DEFAULT_INPUT_DELAY,
+                |delay| delay.parse().expect("Can't parse input delay"));
+    let mut tick = 0usize;
+
     let virtual_dimensions = (VIRTUAL_WIDHT, VIRTUAL_HEIGHT);
-    let frame_rate_loop_duration = Duration::from_millis(1_000u64 / FRAME_RATE);
+    let tick_duration = Duration::new(0, 1_000_000_000 / TICKS_PER_SECOND);
+    let max_accumulated_duration = tick_duration * MAX_TICKS_PER_FRAME;
     let sprites = sprites::SpritesData::new(virtual_dimensions);
-    let mut scene = scene::Scene::new(&sprites);
+
+    if args.iter().any(|arg| arg == "--headless") {
+        let ticks = arg_value(&args, "--ticks")
+            .map(|ticks| ticks.parse().expect("Can't parse ticks count"));
+        if args.iter().any(|arg| arg == "--netplay-test") {
+            let (latency, jitter, loss) = network_conditions(&args)
+                .unwrap_or((Duration::from_millis(100), Duration::from_millis(30), 0.1));
+            let result = headless::run_netplay_test(&sprites,
+                                                    seed,
+                                                    tick_duration,
+                                                    ticks,
+                                                    input_delay,
+                                                    latency,
+                                                    jitter,
+                                                    loss);
+            let in_sync = match result {
+                Ok(stats) => {
+                    print_netplay_stats(seed, &stats);
+                    stats.in_sync()
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    false
+                }
+            };
+            std::process::exit(if in_sync { 0 } else { 1 });
+        }
+        let mut recording = record_path
+            .as_ref()
+            .map(|_| replay::Replay::new(seed, players).expect("Can't record replay"));
+        let stats = match playback {
+            Some(ref playback) => {
+                headless::run(&sprites,
+                              seed,
+                              players,
+                              tick_duration,
+                              ticks,
+                              &mut replay::ReplayInput::new(playback),
+                              recording.as_mut())
+            }
+            None => {
+                headless::run(&sprites,
+                              seed,
+                              players,
+                              tick_duration,
+                              ticks,
+                              &mut input::ScriptedInput::new(headless::scripted_input),
+                              recording.as_mut())
+            }
+        };
+        print_run_stats(seed, tick_duration, &stats);
+        if let (Some(path), Some(recording)) = (record_path, recording) {
+            save_recording(&recording, &path, 0);
+        }
+        return;
+    }
+
+    let bindings_path = arg_value(&args, "--bindings").unwrap_or_else(|| BINDINGS_PATH.to_string());
+    let bindings = if std::path::Path::new(&bindings_path).exists() {
+        input::Bindings::load(&bindings_path).expect("Can't load bindings")
+    } else {
+        input::Bindings::new()
+    };
+
+    let mut session = if let Some(port) = arg_value(&args, "--host") {
+        let port = port.parse().expect("Can't parse port");
+        let transport = netplay::UdpTransport::bind(port).expect("Can't open netplay socket");
+        println!("Waiting for a peer to join");
+        let session = netplay::host(netplay_transport(&args, transport), seed, input_delay)
+            .expect("Can't host netplay");
+        println!("Peer joined");
+        Some(session)
+    } else if let Some(addr) = arg_value(&args, "--connect") {
+        let transport = netplay::UdpTransport::connect(addr).expect("Can't open netplay socket");
+        println!("Joining the host");
+        let session = netplay::join(netplay_transport(&args, transport), input_delay)
+            .expect("Can't join netplay");
+        println!("Joined with seed {}", session.seed());
+        Some(session)
+    } else {
+        None
+    };
+    if session.is_some() && playback.is_some() {
+        panic!("Can't play a replay during netplay");
+    }
+    let practice = args.iter().any(|arg| arg == "--practice");
+    if practice && (session.is_some() || playback.is_some() || record_path.is_some()) {
+        panic!("Can't use save states with replays or netplay");
+    }
+    let save_state_path = arg_value(&args, "--save-state")
+        .unwrap_or_else(|| SAVE_STATE_PATH.to_string());
+    let mut saved_state = if practice {
+        load_saved_state(&save_state_path)
+    } else {
+        None
+    };
+    let (seed, players) = match session {
+        Some(ref session) => (session.seed(), netplay::PLAYERS),
+        None => (seed, players),
+    };
+    let mut recording = record_path
+        .as_ref()
+        .map(|_| replay::Replay::new(seed, players).expect("Can't record replay"));
+    let mut recorded_runs = 0;
+
+    let shared_lives = args.iter().any(|arg| arg == "--shared-lives");
+    let new_scene = |seed| {
+        let mut scene = scene::Scene::with_players(&sprites, seed, players);
+        if shared_lives {
+            scene.set_lives_mode(scene::LivesMode::Shared);
+        }
+        scene
+    };
+    let mut scene = new_scene(seed);
+    let mut game = game::Game::new(session.is_none() && playback.is_none());
+    game.set_pause_enabled(session.is_none());
     let window = create_window();
     let mut input_poller =
-        input::InputPoller::new(window.get_window().expect("Can't get window ref"));
+        input::InputPoller::new(window.get_window().expect("Can't get window ref"), bindings);
+    input_poller.set_players(if session.is_some() { 1 } else { players });
+    if let Some(gamepads) = arg_value(&args, "--gamepads") {
+        for (player, gamepad) in gamepads.split(',').enumerate() {
+            let selection = match gamepad {
+                "auto" => input::GamepadSelection::Auto,
+                "none" => input::GamepadSelection::Disabled,
+                id => input::GamepadSelection::Fixed(id.parse().expect("Can't parse gamepad id")),
+            };
+            input_poller.select_gamepad(player, selection);
+        }
+    }
+    let mut replay_input = playback.as_ref().map(replay::ReplayInput::new);
     let mut instant = Instant::now();
+    let mut accumulated_duration = Duration::new(0, 0);
     let mut renderer = display::Renderer::new(&window, &sprites, virtual_dimensions);
     let mut frame_counter = 0usize;
     let mut frame_counter_instant = Instant::now();
-    const FRAMES_TO_COUNT: usize = 600;
+    let debug_hud = args.iter().any(|arg| arg == "--debug-hud");
+    let mut high_score = load_high_score(HIGH_SCORE_PATH);
+    renderer.set_high_score(high_score);
 
     'main_loop: loop {
         let mut new_instant = Instant::now();
-        let mut duration = new_instant - instant;
-        if duration < frame_rate_loop_duration {
-            std::thread::sleep(frame_rate_loop_duration - duration);
+        let elapsed = accumulated_duration + (new_instant - instant);
+        if elapsed < tick_duration {
+            std::thread::sleep(tick_duration - elapsed);
             new_instant = Instant::now();
-            duration = new_instant - instant;
+        }
+        accumulated_duration += new_instant - instant;
+        if accumulated_duration > max_accumulated_duration {
+            accumulated_duration = max_accumulated_duration;
         }
         instant = new_instant;
 
-        input_poller.poll_events();
-        if input_poller.exit() {
-            break 'main_loop;
+        while accumulated_duration >= tick_duration {
+            input_poller.poll_events();
+            if input_poller.exit() {
+                break 'main_loop;
+            }
+            if input_poller.take_bindings_changed() {
+                if let Err(err) = input_poller.bindings().save(&bindings_path) {
+                    eprintln!("{}", err);
+                }
+            }
+            if input_poller.is_rebinding() {
+                accumulated_duration -= tick_duration;
+                // Keeps the link alive so the peer doesn't time out while bindings are edited.
+                if let Some(ref mut session) = session {
+                    if let Err(err) = session.poll(&mut scene, tick_duration) {
+                        eprintln!("Netplay stopped: {}", err);
+                        break 'main_loop;
+                    }
+                }
+                continue;
+            }
+            let in_game = game.state() != game::GameState::Title;
+            if input_poller.take_save_state_request() && practice && in_game {
+                let state = scene.snapshot();
+                match save_state(&save_state_path, &state) {
+                    Ok(()) => println!("State saved"),
+                    Err(err) => eprintln!("{}", err),
+                }
+                saved_state = Some(state);
+            }
+            if input_poller.take_load_state_request() && practice && in_game {
+                match saved_state {
+                    Some(ref state) => {
+                        match scene.restore(state) {
+                            Ok(()) => println!("State loaded"),
+                            Err(err) => eprintln!("Can't load state: {}", err),
+                        }
+                    }
+                    None => eprintln!("No saved state"),
+                }
+            }
+            let local_inputs = input::player_inputs(&input_poller, input_poller.players());
+            accumulated_duration -= tick_duration;
+            match game.update(&local_inputs, scene.game_over(), tick_duration) {
+                game::Step::Idle => continue,
+                game::Step::NewGame => {
+                    if playback.is_none() && best_score(&scene) > high_score {
+                        high_score = best_score(&scene);
+                        if let Err(err) = save_high_score(HIGH_SCORE_PATH, high_score) {
+                            eprintln!("{}", err);
+                        }
+                        renderer.set_high_score(high_score);
+                    }
+                    match (record_path.as_ref(), recording.as_ref()) {
+                        (Some(path), Some(recording)) if recording.ticks() > 0 => {
+                            save_recording(recording, path, recorded_runs);
+                            recorded_runs += 1;
+                        }
+                        _ => {}
+                    }
+                    let seed = fixed_seed.unwrap_or_else(time_seed);
+                    scene = new_scene(seed);
+                    recording = record_path
+                        .as_ref()
+                        .map(|_| replay::Replay::new(seed, players).expect("Can't record replay"));
+                    tick = 0;
+                    continue;
+                }
+                game::Step::Tick => {}
+            }
+            if let Some(ref mut session) = session {
+                match session.advance(&mut scene, local_inputs[0], tick_duration) {
+                    Ok(true) => tick += 1,
+                    Ok(false) => {}
+                    Err(err) => {
+                        eprintln!("Netplay stopped: {}", err);
+                        break 'main_loop;
+                    }
+                }
+                continue;
+            }
+            let input: &mut InputSource = match replay_input {
+                Some(ref mut replay_input) => {
+                    replay_input.poll_events();
+                    replay_input
+                }
+                None => &mut input_poller,
+            };
+            if input.exit() {
+                break 'main_loop;
+            }
+            let inputs = input::player_inputs(input, players);
+            if let Some(ref mut recording) = recording {
+                recording.record(&inputs);
+            }
+            scene.tick(&inputs, tick_duration);
+            tick += 1;
         }
-        scene.tick(&input_poller, duration);
-        renderer.render(&window, &scene);
+        let alpha = match game.state() {
+            game::GameState::Playing |
+            game::GameState::GameOver => {
+                accumulated_duration.subsec_nanos() as f32 / tick_duration.subsec_nanos() as f32
+            }
+            game::GameState::Title | game::GameState::Paused => 1.0,
+        };
+        let prompt = input_poller
+            .rebinding_action()
+            .map(|action| format!("PRESS KEY FOR {}", input::bindings::action_name(action)));
+        let texts = display::menu_texts(&game,
+                                        &scene,
+                                        prompt.as_ref().map(|prompt| prompt.as_str()),
+                                        virtual_dimensions.into());
+        renderer.render(&window, &scene, game.state(), &texts, alpha);
 
         frame_counter += 1;
         if frame_counter >= FRAMES_TO_COUNT {
             let new_frame_instant = Instant::now();
             let duration = new_frame_instant - frame_counter_instant;
-            println!("{} fps, {} objects",
-                     frame_counter as f32 /
-                     (duration.as_secs() as f32 +
-                      duration.subsec_nanos() as f32 / 1_000_000_000.0),
-                     scene.total_objects());
+            if debug_hud {
+                renderer.set_fps(Some(frame_counter as f32 / util::seconds(duration)));
+            }
             frame_counter = 0;
             frame_counter_instant = Instant::now();
         }
     }
+
+    if playback.is_none() && best_score(&scene) > high_score {
+        if let Err(err) = save_high_score(HIGH_SCORE_PATH, best_score(&scene)) {
+            eprintln!("{}", err);
+        }
+    }
+    if playback.is_some() {
+        println!("Replay finished after {} ticks, score {}", tick, scene.score());
+    }
+    if let Some(ref session) = session {
+        println!("Netplay finished after {} ticks, {} rollbacks, {} stalls, {} dropped packets",
+                 session.tick(),
+                 session.rollbacks(),
+                 session.stalls(),
+                 session.dropped_packets());
+        if let Some(ref mut recording) = recording {
+            for tick in 0..session.confirmed_ticks().min(session.tick()) {
+                recording.record(&session.inputs(tick));
+            }
+        }
+    }
+    if let (Some(path), Some(recording)) = (record_path, recording) {
+        save_recording(&recording, &path, recorded_runs);
+    }
 }
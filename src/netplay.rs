@@ -0,0 +1,580 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use input::InputState;
+use scene::{Scene, SceneState};
+use util::{seconds, Rng};
+
+const NET_MAGIC: &'static str = "YAVSS_NET";
+const PROTOCOL_VERSION: u32 = 1;
+const MAX_PACKET_SIZE: usize = 4096;
+const MAX_INPUTS_PER_PACKET: usize = 32;
+const MAX_PREDICTED_TICKS: usize = 8;
+const HANDSHAKE_INTERVAL_MS: u64 = 100;
+const HANDSHAKE_TIMEOUT_S: u64 = 30;
+const PEER_TIMEOUT_S: f32 = 5.0;
+pub const PLAYERS: usize = 2;
+pub const DEFAULT_INPUT_DELAY: usize = 2;
+
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]);
+    fn receive(&mut self) -> Option<Vec<u8>>;
+
+    fn update(&mut self, _elapsed: Duration) {}
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+}
+
+impl UdpTransport {
+    pub fn bind(port: u16) -> Result<UdpTransport, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Can't bind UDP port {}: {}", port, e))?;
+        UdpTransport::new(socket, None)
+    }
+
+    pub fn connect<A>(addr: A) -> Result<UdpTransport, String>
+        where A: ToSocketAddrs
+    {
+        let peer = addr.to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| "Can't resolve peer address".to_string())?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0))
+            .map_err(|e| format!("Can't bind UDP socket: {}", e))?;
+        UdpTransport::new(socket, Some(peer))
+    }
+
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>) -> Result<UdpTransport, String> {
+        socket
+            .set_nonblocking(true)
+            .map_err(|e| format!("Can't make UDP socket non-blocking: {}", e))?;
+        Ok(UdpTransport {
+               socket: socket,
+               peer: peer,
+           })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) {
+        if let Some(peer) = self.peer {
+            let _ = self.socket.send_to(packet, peer);
+        }
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, addr)) => {
+                    // The host accepts the first peer that talks to it.
+                    if self.peer.is_none() {
+                        self.peer = Some(addr);
+                    }
+                    if self.peer == Some(addr) {
+                        return Some(buffer[..size].to_vec());
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return None,
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+pub struct LocalTransport {
+    incoming: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    outgoing: Rc<RefCell<VecDeque<Vec<u8>>>>,
+}
+
+impl LocalTransport {
+    pub fn pair() -> (LocalTransport, LocalTransport) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (LocalTransport {
+             incoming: a.clone(),
+             outgoing: b.clone(),
+         },
+         LocalTransport {
+             incoming: b,
+             outgoing: a,
+         })
+    }
+}
+
+impl Transport for LocalTransport {
+    fn send(&mut self, packet: &[u8]) {
+        self.outgoing.borrow_mut().push_back(packet.to_vec());
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.incoming.borrow_mut().pop_front()
+    }
+}
+
+// Delays, reorders and drops outgoing packets to test the session against a bad network.
+pub struct LossyTransport<T>
+    where T: Transport
+{
+    inner: T,
+    latency_s: f32,
+    jitter_s: f32,
+    loss: f32,
+    rng: Rng,
+    now_s: f32,
+    queue: Vec<(f32, Vec<u8>)>,
+}
+
+impl<T> LossyTransport<T>
+    where T: Transport
+{
+    pub fn new(inner: T,
+               latency: Duration,
+               jitter: Duration,
+               loss: f32,
+               seed: u64)
+               -> LossyTransport<T> {
+        LossyTransport {
+            inner: inner,
+            latency_s: seconds(latency),
+            jitter_s: seconds(jitter),
+            loss: loss,
+            rng: Rng::new(seed),
+            now_s: 0.0,
+            queue: vec![],
+        }
+    }
+}
+
+impl<T> Transport for LossyTransport<T>
+    where T: Transport
+{
+    fn send(&mut self, packet: &[u8]) {
+        if self.rng.next_f32() < self.loss {
+            return;
+        }
+        let deliver_at = self.now_s + self.latency_s + self.jitter_s * self.rng.next_f32();
+        self.queue.push((deliver_at, packet.to_vec()));
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.inner.receive()
+    }
+
+    fn update(&mut self, elapsed: Duration) {
+        self.now_s += seconds(elapsed);
+        let now_s = self.now_s;
+        let inner = &mut self.inner;
+        self.queue
+            .retain(|&(deliver_at, ref packet)| if deliver_at <= now_s {
+                        inner.send(packet);
+                        false
+                    } else {
+                        true
+                    });
+        inner.update(elapsed);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Packet {
+    Hello,
+    Welcome(u64),
+    Input {
+        ack: usize,
+        first_tick: usize,
+        inputs: Vec<InputState>,
+    },
+}
+
+impl Packet {
+    fn to_bytes(&self) -> Vec<u8> {
+        let body = match *self {
+            Packet::Hello => "HELLO".to_string(),
+            Packet::Welcome(seed) => format!("WELCOME {}", seed),
+            Packet::Input {
+                ack,
+                first_tick,
+                ref inputs,
+            } => {
+                let inputs = inputs
+                    .iter()
+                    .map(|input| input.to_string())
+                    .collect::<Vec<_>>();
+                format!("INPUT {} {} {}", ack, first_tick, inputs.join(";"))
+            }
+        };
+        format!("{} {} {}", NET_MAGIC, PROTOCOL_VERSION, body).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Packet, String> {
+        let text = ::std::str::from_utf8(bytes).map_err(|_| "Can't decode packet".to_string())?;
+        let mut words = text.splitn(6, ' ');
+        if words.next() != Some(NET_MAGIC) {
+            return Err("Unknown packet".to_string());
+        }
+        if words.next() != Some(PROTOCOL_VERSION.to_string().as_str()) {
+            return Err("Unsupported protocol version".to_string());
+        }
+        let number = |word: Option<&str>| {
+            word.and_then(|word| u64::from_str(word).ok())
+                .ok_or_else(|| format!("Can't parse packet '{}'", text))
+        };
+        match words.next() {
+            Some("HELLO") => Ok(Packet::Hello),
+            Some("WELCOME") => Ok(Packet::Welcome(number(words.next())?)),
+            Some("INPUT") => {
+                let ack = number(words.next())? as usize;
+                let first_tick = number(words.next())? as usize;
+                let inputs = match words.next() {
+                    Some(inputs) if !inputs.is_empty() => {
+                        inputs
+                            .split(';')
+                            .map(InputState::from_str)
+                            .collect::<Result<Vec<_>, _>>()?
+                    }
+                    _ => vec![],
+                };
+                Ok(Packet::Input {
+                       ack: ack,
+                       first_tick: first_tick,
+                       inputs: inputs,
+                   })
+            }
+            _ => Err(format!("Can't parse packet '{}'", text)),
+        }
+    }
+}
+
+pub struct Session {
+    transport: Box<Transport>,
+    local_player: usize,
+    seed: u64,
+    tick: usize,
+    local_inputs: Vec<InputState>,
+    remote_inputs: Vec<InputState>,
+    used_remote_inputs: Vec<InputState>,
+    remote_ack: usize,
+    states: VecDeque<SceneState>,
+    states_start: usize,
+    rollbacks: usize,
+    resimulated_ticks: usize,
+    stalls: usize,
+    dropped_packets: usize,
+    silence_s: f32,
+}
+
+impl Session {
+    pub fn new(transport: Box<Transport>,
+               local_player: usize,
+               seed: u64,
+               input_delay: usize)
+               -> Session {
+        Session {
+            transport: transport,
+            local_player: local_player,
+            seed: seed,
+            tick: 0,
+            local_inputs: vec![Default::default(); input_delay],
+            remote_inputs: vec![],
+            used_remote_inputs: vec![],
+            remote_ack: 0,
+            states: VecDeque::new(),
+            states_start: 0,
+            rollbacks: 0,
+            resimulated_ticks: 0,
+            stalls: 0,
+            dropped_packets: 0,
+            silence_s: 0.0,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn confirmed_ticks(&self) -> usize {
+        self.remote_inputs.len().min(self.local_inputs.len())
+    }
+
+    pub fn rollbacks(&self) -> usize {
+        self.rollbacks
+    }
+
+    pub fn resimulated_ticks(&self) -> usize {
+        self.resimulated_ticks
+    }
+
+    pub fn stalls(&self) -> usize {
+        self.stalls
+    }
+
+    pub fn dropped_packets(&self) -> usize {
+        self.dropped_packets
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.remote_inputs.len() >= self.tick && self.remote_ack >= self.tick
+    }
+
+    pub fn inputs(&self, tick: usize) -> Vec<InputState> {
+        let local = self.local_inputs.get(tick).cloned().unwrap_or_default();
+        let remote = self.remote_inputs
+            .get(tick)
+            .or_else(|| self.remote_inputs.last())
+            .cloned()
+            .unwrap_or_default();
+        (0..PLAYERS)
+            .map(|player| if player == self.local_player {
+                     local
+                 } else {
+                     remote
+                 })
+            .collect()
+    }
+
+    // Returns false when the remote side is too far behind and the tick had to be skipped, and an
+    // error once nothing has been heard from the remote side for too long.
+    pub fn advance(&mut self,
+                   scene: &mut Scene,
+                   local_input: InputState,
+                   duration: Duration)
+                   -> Result<bool, String> {
+        self.transport.update(duration);
+        self.receive(scene, duration)?;
+        if self.tick >= self.remote_inputs.len() + MAX_PREDICTED_TICKS {
+            self.stalls += 1;
+            self.send_inputs();
+            return Ok(false);
+        }
+        self.local_inputs.push(local_input);
+        self.send_inputs();
+        self.run_tick(scene, duration);
+        while self.states_start < self.remote_inputs.len().min(self.tick) {
+            self.states.pop_front();
+            self.states_start += 1;
+        }
+        Ok(true)
+    }
+
+    pub fn poll(&mut self, scene: &mut Scene, duration: Duration) -> Result<(), String> {
+        self.transport.update(duration);
+        self.receive(scene, duration)?;
+        self.send_inputs();
+        Ok(())
+    }
+
+    fn run_tick(&mut self, scene: &mut Scene, duration: Duration) {
+        let state = scene.save_state();
+        let index = self.tick - self.states_start;
+        if index < self.states.len() {
+            self.states[index] = state;
+        } else {
+            self.states.push_back(state);
+        }
+
+        let inputs = self.inputs(self.tick);
+        let remote = inputs[1 - self.local_player];
+        if self.tick < self.used_remote_inputs.len() {
+            self.used_remote_inputs[self.tick] = remote;
+        } else {
+            self.used_remote_inputs.push(remote);
+        }
+        scene.tick(&inputs, duration);
+        self.tick += 1;
+    }
+
+    fn receive(&mut self, scene: &mut Scene, duration: Duration) -> Result<(), String> {
+        let mut rollback_tick: Option<usize> = None;
+        self.silence_s += seconds(duration);
+        while let Some(bytes) = self.transport.receive() {
+            let packet = Packet::parse(&bytes);
+            if packet.is_ok() {
+                self.silence_s = 0.0;
+            }
+            match packet {
+                Ok(Packet::Hello) => {
+                    if self.local_player == 0 {
+                        self.transport.send(&Packet::Welcome(self.seed).to_bytes());
+                    }
+                }
+                Ok(Packet::Welcome(..)) => {}
+                Ok(Packet::Input {
+                       ack,
+                       first_tick,
+                       inputs,
+                   }) => {
+                    self.remote_ack = self.remote_ack.max(ack);
+                    for (i, input) in inputs.into_iter().enumerate() {
+                        let tick = first_tick + i;
+                        if tick != self.remote_inputs.len() {
+                            continue;
+                        }
+                        self.remote_inputs.push(input);
+                        if tick < self.tick && self.used_remote_inputs[tick] != input {
+                            rollback_tick = Some(rollback_tick.map_or(tick, |t| t.min(tick)));
+                        }
+                    }
+                }
+                Err(_) => self.dropped_packets += 1,
+            }
+        }
+        if let Some(tick) = rollback_tick {
+            self.rollback(scene, tick, duration);
+        }
+        if self.silence_s >= PEER_TIMEOUT_S {
+            return Err("Peer disconnected".to_string());
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self, scene: &mut Scene, tick: usize, duration: Duration) {
+        let end = self.tick;
+        scene.load_state(&self.states[tick - self.states_start]);
+        self.tick = tick;
+        while self.tick < end {
+            self.run_tick(scene, duration);
+        }
+        self.rollbacks += 1;
+        self.resimulated_ticks += end - tick;
+    }
+
+    fn send_inputs(&mut self) {
+        let first_tick = self.remote_ack.min(self.local_inputs.len());
+        let inputs = self.local_inputs[first_tick..]
+            .iter()
+            .take(MAX_INPUTS_PER_PACKET)
+            .cloned()
+            .collect();
+        let packet = Packet::Input {
+            ack: self.remote_inputs.len(),
+            first_tick: first_tick,
+            inputs: inputs,
+        };
+        self.transport.send(&packet.to_bytes());
+    }
+}
+
+fn handshake_timed_out(start: Instant) -> bool {
+    Instant::now() - start > Duration::from_secs(HANDSHAKE_TIMEOUT_S)
+}
+
+pub fn host(mut transport: Box<Transport>,
+            seed: u64,
+            input_delay: usize)
+            -> Result<Session, String> {
+    use std::thread;
+
+    let interval = Duration::from_millis(HANDSHAKE_INTERVAL_MS);
+    let start = Instant::now();
+    loop {
+        while let Some(bytes) = transport.receive() {
+            if let Ok(Packet::Hello) = Packet::parse(&bytes) {
+                transport.send(&Packet::Welcome(seed).to_bytes());
+                return Ok(Session::new(transport, 0, seed, input_delay));
+            }
+        }
+        if handshake_timed_out(start) {
+            return Err("No peer joined".to_string());
+        }
+        thread::sleep(interval);
+        transport.update(interval);
+    }
+}
+
+pub fn join(mut transport: Box<Transport>, input_delay: usize) -> Result<Session, String> {
+    use std::thread;
+
+    let interval = Duration::from_millis(HANDSHAKE_INTERVAL_MS);
+    let start = Instant::now();
+    loop {
+        transport.send(&Packet::Hello.to_bytes());
+        thread::sleep(interval);
+        transport.update(interval);
+        while let Some(bytes) = transport.receive() {
+            if let Ok(Packet::Welcome(seed)) = Packet::parse(&bytes) {
+                return Ok(Session::new(transport, 1, seed, input_delay));
+            }
+        }
+        if handshake_timed_out(start) {
+            return Err("Can't reach the host".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sprites::SpritesData;
+
+    fn round_trip(packet: Packet) {
+        assert_eq!(Packet::parse(&packet.to_bytes()), Ok(packet));
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        round_trip(Packet::Hello);
+        round_trip(Packet::Welcome(0xDEAD_BEEF_1234));
+        round_trip(Packet::Input {
+                       ack: 3,
+                       first_tick: 7,
+                       inputs: vec![],
+                   });
+        round_trip(Packet::Input {
+                       ack: 10,
+                       first_tick: 12,
+                       inputs: vec![InputState::new(0.5, -1.0, true, false, true),
+                                    Default::default()],
+                   });
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        assert!(Packet::parse(b"").is_err());
+        assert!(Packet::parse(b"\xff\xfe").is_err());
+        assert!(Packet::parse(b"OTHER_GAME 1 HELLO").is_err());
+        assert!(Packet::parse(format!("{} 999 HELLO", NET_MAGIC).as_bytes()).is_err());
+        assert!(Packet::parse(format!("{} {} WELCOME", NET_MAGIC, PROTOCOL_VERSION).as_bytes())
+                    .is_err());
+        assert!(Packet::parse(format!("{} {} INPUT 1 2 0 0 0", NET_MAGIC, PROTOCOL_VERSION)
+                                  .as_bytes())
+                        .is_err());
+    }
+
+    #[test]
+    fn reports_silent_peer() {
+        let sprites = SpritesData::new((200, 200));
+        let duration = Duration::from_millis(16);
+        let (host_link, client_link) = LocalTransport::pair();
+        let mut host = Session::new(Box::new(host_link), 0, 1, DEFAULT_INPUT_DELAY);
+        let mut client = Session::new(Box::new(client_link), 1, 1, DEFAULT_INPUT_DELAY);
+        let mut host_scene = Scene::with_players(&sprites, 1, PLAYERS);
+        let mut client_scene = Scene::with_players(&sprites, 1, PLAYERS);
+        for _ in 0..60 {
+            assert!(host.advance(&mut host_scene, Default::default(), duration).is_ok());
+            assert!(client.advance(&mut client_scene, Default::default(), duration).is_ok());
+        }
+        let mut silent_ticks = 0;
+        while host.advance(&mut host_scene, Default::default(), duration).is_ok() {
+            silent_ticks += 1;
+            assert!(silent_ticks < 1000);
+        }
+        let silence_s = silent_ticks as f32 * seconds(duration);
+        assert!((silence_s - PEER_TIMEOUT_S).abs() < 0.1);
+    }
+}
@@ -0,0 +1,254 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use input::{InputSource, InputState};
+
+const REPLAY_MAGIC: &'static str = "YAVSS_REPLAY";
+const REPLAY_VERSION: u32 = 2;
+const PLAYERS_SEPARATOR: &'static str = " | ";
+const GAME_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    seed: u64,
+    players: usize,
+    inputs: Vec<InputState>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, players: usize) -> Result<Replay, String> {
+        if players == 0 {
+            return Err("Can't replay without players".to_string());
+        }
+        Ok(Replay {
+               seed: seed,
+               players: players,
+               inputs: vec![],
+           })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn players(&self) -> usize {
+        self.players
+    }
+
+    pub fn record(&mut self, inputs: &[InputState]) {
+        for player in 0..self.players {
+            self.inputs.push(inputs.get(player).cloned().unwrap_or_default());
+        }
+    }
+
+    pub fn input(&self, tick: usize, player: usize) -> Option<&InputState> {
+        if player < self.players {
+            self.inputs.get(tick * self.players + player)
+        } else {
+            None
+        }
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.inputs.len() / self.players
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<(), String>
+        where P: AsRef<Path>
+    {
+        let file = File::create(path).map_err(|e| format!("Can't create replay file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        let mut write = || -> ::std::io::Result<()> {
+            writeln!(writer, "{} {} {}", REPLAY_MAGIC, REPLAY_VERSION, GAME_VERSION)?;
+            writeln!(writer, "SEED {}", self.seed)?;
+            writeln!(writer, "PLAYERS {}", self.players)?;
+            for inputs in self.inputs.chunks(self.players) {
+                let inputs = inputs
+                    .iter()
+                    .map(|input| input.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", inputs.join(PLAYERS_SEPARATOR))?;
+            }
+            writer.flush()
+        };
+        write().map_err(|e| format!("Can't write replay file: {}", e))
+    }
+
+    pub fn load<P>(path: P) -> Result<Replay, String>
+        where P: AsRef<Path>
+    {
+        let mut descr = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut descr))
+            .map_err(|e| format!("Can't read replay file: {}", e))?;
+        Replay::from_descr(&descr)
+    }
+
+    pub fn from_descr(descr: &str) -> Result<Replay, String> {
+        let mut lines = descr.lines();
+
+        let header = lines
+            .next()
+            .ok_or_else(|| "Can't find replay header".to_string())?
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        if header.len() != 3 || header[0] != REPLAY_MAGIC {
+            return Err("Can't parse replay header".to_string());
+        }
+        if header[1] != REPLAY_VERSION.to_string() || header[2] != GAME_VERSION {
+            return Err(format!("Unsupported replay version {} {}", header[1], header[2]));
+        }
+
+        let mut header_value = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| {
+                              let words = line.split_whitespace().collect::<Vec<_>>();
+                              if words.len() == 2 && words[0] == name {
+                                  u64::from_str(words[1]).ok()
+                              } else {
+                                  None
+                              }
+                          })
+                .ok_or_else(|| format!("Can't parse replay {}", name.to_lowercase()))
+        };
+        let seed = header_value("SEED")?;
+        let players = header_value("PLAYERS")? as usize;
+
+        let mut replay = Replay::new(seed, players)?;
+        for line in lines {
+            let inputs = line.split(PLAYERS_SEPARATOR.trim()).collect::<Vec<_>>();
+            if inputs.len() != players {
+                return Err(format!("Can't parse replay inputs '{}'", line));
+            }
+            for input in inputs {
+                replay.inputs.push(InputState::from_str(input)?);
+            }
+        }
+        Ok(replay)
+    }
+}
+
+pub struct ReplayInput<'a> {
+    replay: &'a Replay,
+    tick: usize,
+}
+
+impl<'a> ReplayInput<'a> {
+    pub fn new(replay: &'a Replay) -> ReplayInput<'a> {
+        ReplayInput {
+            replay: replay,
+            tick: 0,
+        }
+    }
+}
+
+impl<'a> InputSource for ReplayInput<'a> {
+    fn poll_events(&mut self) {
+        self.tick += 1;
+    }
+
+    fn exit(&self) -> bool {
+        self.tick > self.replay.ticks()
+    }
+
+    fn players(&self) -> usize {
+        self.replay.players()
+    }
+
+    fn player_input(&self, player: usize) -> InputState {
+        self.tick
+            .checked_sub(1)
+            .and_then(|tick| self.replay.input(tick, player))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time::Duration;
+    use headless::scripted_input;
+    use input::{self, ScriptedInput};
+    use scene::Scene;
+    use sprites::SpritesData;
+
+    fn header(magic: &str, version: u32, players: usize) -> String {
+        format!("{} {} {}\nSEED 7\nPLAYERS {}\n", magic, version, GAME_VERSION, players)
+    }
+
+    fn record(players: usize, ticks: usize) -> Replay {
+        let mut replay = Replay::new(7, players).expect("Can't create replay");
+        let mut input = ScriptedInput::new(scripted_input);
+        for _ in 0..ticks {
+            input.poll_events();
+            replay.record(&input::player_inputs(&input, players));
+        }
+        replay
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let replay = record(2, 300);
+        let path = env::temp_dir().join("yavss_replay_round_trip.txt");
+        replay.save(&path).expect("Can't save replay");
+        let loaded = Replay::load(&path).expect("Can't load replay");
+        assert_eq!(loaded.seed(), 7);
+        assert_eq!(loaded.players(), 2);
+        assert_eq!(loaded.ticks(), 300);
+        for tick in 0..300 {
+            for player in 0..2 {
+                assert_eq!(loaded.input(tick, player), replay.input(tick, player));
+            }
+        }
+        assert_eq!(loaded.input(0, 2), None);
+        assert_eq!(loaded.input(300, 0), None);
+    }
+
+    #[test]
+    fn rejects_invalid_replays() {
+        assert!(Replay::new(7, 0).is_err());
+        assert!(Replay::from_descr("").is_err());
+        assert!(Replay::from_descr(&header(REPLAY_MAGIC, REPLAY_VERSION, 1)).is_ok());
+        assert!(Replay::from_descr(&header("YAVSS_REPLAI", REPLAY_VERSION, 1)).is_err());
+        assert!(Replay::from_descr(&header(REPLAY_MAGIC, REPLAY_VERSION + 1, 1)).is_err());
+        assert!(Replay::from_descr(&header(REPLAY_MAGIC, REPLAY_VERSION, 0)).is_err());
+
+        let inputs = "0 0 0 0 1 0 0 | 0 0 0 0 0 0 1\n";
+        let descr = header(REPLAY_MAGIC, REPLAY_VERSION, 2) + inputs;
+        assert_eq!(Replay::from_descr(&descr).map(|replay| replay.ticks()), Ok(1));
+        let descr = header(REPLAY_MAGIC, REPLAY_VERSION, 1) + inputs;
+        assert!(Replay::from_descr(&descr).is_err());
+    }
+
+    #[test]
+    fn replay_reproduces_the_run() {
+        let sprites = SpritesData::new((200, 200));
+        let tick_duration = Duration::new(0, 1_000_000_000 / 60);
+        let replay = record(2, 900);
+
+        let mut scene = Scene::with_players(&sprites, replay.seed(), 2);
+        let mut input = ScriptedInput::new(scripted_input);
+        for _ in 0..900 {
+            input.poll_events();
+            scene.tick(&input::player_inputs(&input, 2), tick_duration);
+        }
+
+        let path = env::temp_dir().join("yavss_replay_reproduces_run.txt");
+        replay.save(&path).expect("Can't save replay");
+        let replay = Replay::load(&path).expect("Can't load replay");
+        let mut replayed = Scene::with_players(&sprites, replay.seed(), replay.players());
+        let mut input = ReplayInput::new(&replay);
+        loop {
+            input.poll_events();
+            if input.exit() {
+                break;
+            }
+            replayed.tick(&input::player_inputs(&input, replay.players()), tick_duration);
+        }
+        assert_eq!(replayed.checksum(), scene.checksum());
+    }
+}
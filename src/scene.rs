@@ -1,29 +1,114 @@
+use std::cell::Cell;
 use std::time::Duration;
-use input::InputPoller;
+use input::Controls;
 use sprites::{SpriteObject, SpriteData, SpritesData};
 use collision::{CollisionDetector, CollisionData};
-use util::{Angle, FPoint};
+use stage::{Stage, Spawn, Behavior, BossPhase, PatternKind};
+use weapon::Weapon;
+use bulletml;
+use snapshot::{Reader, Writer};
+use util::{seconds, Angle, FPoint, Rng};
 
 type CoordValue = f32;
 type Speed = f32; //Screens/s
+type Acceleration = f32; //Screens/s^2
+type AngularVelocity = f32; //Degrees/s
 
 const MIN_X_VALUE: CoordValue = 0.0;
 const MAX_X_VALUE: CoordValue = 1.0;
 const MIN_Y_VALUE: CoordValue = 0.0;
 const MAX_Y_VALUE: CoordValue = 1.0;
 
-#[derive(Debug, Clone, Copy)]
+const EXPLOSION_FRAMES_COUNT: u32 = 4;
+const DEFAULT_RANK: f32 = 0.5;
+const STARTING_LIVES: u32 = 3;
+const STARTING_BOMBS: u32 = 3;
+const BOMB_DAMAGE: u32 = 16;
+const PLAYER_SPAWN_Y: CoordValue = 0.8;
+const PLAYER_SPAWN_SPACING: CoordValue = 0.3;
+const ENEMY_BULLET_POINTS: u64 = 10;
+const GRAZE_POINTS: u64 = 20;
+const GRAZE_DISTANCE: f32 = 6.0;
+const SCORE_ITEM_POINTS: u64 = 500;
+const POWER_ITEM_POINTS: u64 = 1000;
+const POWER_LOST_ON_DEATH: u32 = 2;
+const CHAIN_STEP: u32 = 8;
+const MAX_MULTIPLIER: u32 = 8;
+const FOCUSED_SPREAD: f32 = 0.3;
+const SNAPSHOT_MAGIC: &'static [u8] = b"YAVSS_SNAPSHOT";
+const SNAPSHOT_VERSION: u32 = 2;
+pub const DEFAULT_SEED: u64 = 0x5EED;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PlayerState {
     Normal,
     TiltedLeft,
     TiltedRight,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerStatus {
+    Alive,
+    Dead,
+    GameOver,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LivesMode {
+    Separate,
+    Shared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ObjectType {
     Player(PlayerState),
     PlayerBullet(u32),
     EnemyBullet(u32),
+    Enemy,
+    Boss,
+    Explosion(u32),
+    ScoreItem(u32),
+    PowerItem(u32),
+    Hitbox,
+}
+
+impl ObjectType {
+    fn write(&self, writer: &mut Writer) {
+        let (tag, value) = match *self {
+            ObjectType::Player(PlayerState::Normal) => (0, 0),
+            ObjectType::Player(PlayerState::TiltedLeft) => (0, 1),
+            ObjectType::Player(PlayerState::TiltedRight) => (0, 2),
+            ObjectType::PlayerBullet(frame) => (1, frame),
+            ObjectType::EnemyBullet(frame) => (2, frame),
+            ObjectType::Enemy => (3, 0),
+            ObjectType::Boss => (4, 0),
+            ObjectType::Explosion(frame) => (5, frame),
+            ObjectType::ScoreItem(frame) => (6, frame),
+            ObjectType::PowerItem(frame) => (7, frame),
+            ObjectType::Hitbox => (8, 0),
+        };
+        writer.write_u8(tag);
+        writer.write_u32(value);
+    }
+
+    fn read(reader: &mut Reader) -> Result<ObjectType, String> {
+        let tag = reader.read_u8()?;
+        let value = reader.read_u32()?;
+        match (tag, value) {
+            (0, 0) => Ok(ObjectType::Player(PlayerState::Normal)),
+            (0, 1) => Ok(ObjectType::Player(PlayerState::TiltedLeft)),
+            (0, 2) => Ok(ObjectType::Player(PlayerState::TiltedRight)),
+            (1, frame) => Ok(ObjectType::PlayerBullet(frame)),
+            (2, frame) => Ok(ObjectType::EnemyBullet(frame)),
+            (3, _) => Ok(ObjectType::Enemy),
+            (4, _) => Ok(ObjectType::Boss),
+            (5, frame) => Ok(ObjectType::Explosion(frame)),
+            (6, frame) => Ok(ObjectType::ScoreItem(frame)),
+            (7, frame) => Ok(ObjectType::PowerItem(frame)),
+            (8, _) => Ok(ObjectType::Hitbox),
+            _ => Err(format!("Can't parse object type {} {}", tag, value)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,8 +116,22 @@ pub struct SceneObject {
     pub object_type: ObjectType,
     pub pos: FPoint,
     pub direction_angle: Angle,
+    pub speed: Speed,
+    pub acceleration: Acceleration,
+    pub angular_velocity: AngularVelocity,
+    pub min_speed: Option<Speed>,
+    pub max_speed: Option<Speed>,
     pub to_delete: bool,
+    pub grazed: bool,
+    pub health: u32,
+    pub damage: u32,
+    pub owner: usize,
+    pub lifetime: f32,
+    pub firing_timeout: f32,
+    pub behavior: Option<Behavior>,
+    pub bulletml: Option<bulletml::Runner>,
     collision_data: CollisionData,
+    prev_pos: FPoint,
     sprite_angle: Angle,
     sprite_scale: FPoint,
 }
@@ -48,11 +147,26 @@ impl SceneObject {
               P2: Into<FPoint>
     {
         let sprite_scale = sprite_scale.into();
+        let pos = pos.into();
         SceneObject {
             object_type: object_type,
-            pos: pos.into(),
+            pos: pos,
             direction_angle: Angle::from_deg(sprite_angle.as_deg() - 90.0),
+            speed: 0.0,
+            acceleration: 0.0,
+            angular_velocity: 0.0,
+            min_speed: None,
+            max_speed: None,
             to_delete: false,
+            grazed: false,
+            health: 1,
+            damage: 1,
+            owner: 0,
+            lifetime: 0.0,
+            firing_timeout: 0.0,
+            behavior: None,
+            bulletml: None,
+            prev_pos: pos,
             sprite_angle: sprite_angle,
             collision_data: CollisionData::new(sprites_data_cache.sprite_data(&object_type),
                                                sprite_angle,
@@ -61,6 +175,10 @@ impl SceneObject {
         }
     }
 
+    pub fn interpolated_pos(&self, alpha: f32) -> FPoint {
+        self.prev_pos + (self.pos - self.prev_pos) * FPoint::new(alpha, alpha)
+    }
+
     pub fn sprite_angle(&self) -> Angle {
         self.sprite_angle
     }
@@ -89,6 +207,392 @@ impl SceneObject {
                                                  sprite_scale);
         self.sprite_scale = sprite_scale;
     }
+
+    fn write(&self, writer: &mut Writer) {
+        self.object_type.write(writer);
+        writer.write_point(self.pos);
+        writer.write_point(self.prev_pos);
+        writer.write_angle(self.sprite_angle);
+        writer.write_point(self.sprite_scale);
+        writer.write_angle(self.direction_angle);
+        writer.write_f32(self.speed);
+        writer.write_f32(self.acceleration);
+        writer.write_f32(self.angular_velocity);
+        writer.write_option_f32(self.min_speed);
+        writer.write_option_f32(self.max_speed);
+        writer.write_bool(self.to_delete);
+        writer.write_bool(self.grazed);
+        writer.write_u32(self.health);
+        writer.write_u32(self.damage);
+        writer.write_usize(self.owner);
+        writer.write_f32(self.lifetime);
+        writer.write_f32(self.firing_timeout);
+        writer.write_bool(self.behavior.is_some());
+        if let Some(behavior) = self.behavior {
+            writer.write_usize(behavior.enemy_kind);
+            writer.write_usize(behavior.path);
+            writer.write_usize(behavior.pattern);
+        }
+        writer.write_bool(self.bulletml.is_some());
+        if let Some(ref runner) = self.bulletml {
+            runner.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader, context: &SnapshotContext) -> Result<SceneObject, String> {
+        let object_type = ObjectType::read(reader)?;
+        let pos = reader.read_point()?;
+        let prev_pos = reader.read_point()?;
+        let sprite_angle = reader.read_angle()?;
+        let sprite_scale = reader.read_point()?;
+        let mut object = SceneObject::new(context.sprite_data_cache,
+                                          object_type,
+                                          pos,
+                                          sprite_angle,
+                                          sprite_scale);
+        object.prev_pos = prev_pos;
+        object.direction_angle = reader.read_angle()?;
+        object.speed = reader.read_f32()?;
+        object.acceleration = reader.read_f32()?;
+        object.angular_velocity = reader.read_f32()?;
+        object.min_speed = reader.read_option_f32()?;
+        object.max_speed = reader.read_option_f32()?;
+        object.to_delete = reader.read_bool()?;
+        object.grazed = reader.read_bool()?;
+        object.health = reader.read_u32()?;
+        object.damage = reader.read_u32()?;
+        object.owner = reader.read_index(context.players, "owner")?;
+        object.lifetime = reader.read_f32()?;
+        object.firing_timeout = reader.read_f32()?;
+        if reader.read_bool()? {
+            let stage = context.stage;
+            object.behavior =
+                Some(Behavior {
+                         enemy_kind: reader.read_index(stage.enemy_kinds().len(), "enemy kind")?,
+                         path: reader.read_index(stage.paths().len(), "path")?,
+                         pattern: reader.read_index(stage.patterns().len(), "pattern")?,
+                     });
+        }
+        if reader.read_bool()? {
+            object.bulletml = Some(bulletml::Runner::read(reader, context.bulletml_library)?);
+        }
+        Ok(object)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Player {
+    scene_object: SceneObject,
+    hitbox_scene_object: SceneObject,
+    spawn_point: FPoint,
+    status: PlayerStatus,
+    focused: bool,
+    weapon_level: u32,
+    lives: u32,
+    bombs: u32,
+    bomb_was_pressed: bool,
+    fire_was_pressed: bool,
+    respawn_timeout: f32,
+    invulnerability_timeout: f32,
+    continue_timeout: f32,
+    firing_timeout: f32,
+    score: u64,
+    chain: u32,
+    chain_timeout: f32,
+    grazes: u32,
+    continues: u32,
+}
+
+impl Player {
+    fn new(sprites_data_cache: &SpriteDataCache,
+           spawn_point: FPoint,
+           firing_timeout: f32)
+           -> Player {
+        Player {
+            scene_object: SceneObject::new(sprites_data_cache,
+                                           ObjectType::Player(PlayerState::Normal),
+                                           spawn_point,
+                                           Angle::from_deg(0.0),
+                                           (1.0, 1.0)),
+            hitbox_scene_object: SceneObject::new(sprites_data_cache,
+                                                  ObjectType::Hitbox,
+                                                  spawn_point,
+                                                  Angle::from_deg(0.0),
+                                                  (1.0, 1.0)),
+            spawn_point: spawn_point,
+            status: PlayerStatus::Alive,
+            focused: false,
+            weapon_level: 0,
+            lives: STARTING_LIVES,
+            bombs: STARTING_BOMBS,
+            bomb_was_pressed: false,
+            fire_was_pressed: false,
+            respawn_timeout: 0.0,
+            invulnerability_timeout: 0.0,
+            continue_timeout: 0.0,
+            firing_timeout: firing_timeout,
+            score: 0,
+            chain: 0,
+            chain_timeout: 0.0,
+            grazes: 0,
+            continues: 0,
+        }
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        self.status
+    }
+
+    pub fn lives(&self) -> u32 {
+        self.lives
+    }
+
+    pub fn bombs(&self) -> u32 {
+        self.bombs
+    }
+
+    pub fn weapon_level(&self) -> u32 {
+        self.weapon_level
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn chain(&self) -> u32 {
+        self.chain
+    }
+
+    pub fn multiplier(&self) -> u32 {
+        (1 + self.chain / CHAIN_STEP).min(MAX_MULTIPLIER)
+    }
+
+    pub fn grazes(&self) -> u32 {
+        self.grazes
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn pos(&self) -> FPoint {
+        self.scene_object.pos
+    }
+
+    pub fn continues(&self) -> u32 {
+        self.continues
+    }
+
+    pub fn continue_timeout(&self) -> Option<f32> {
+        if self.can_continue() {
+            Some(self.continue_timeout)
+        } else {
+            None
+        }
+    }
+
+    fn can_continue(&self) -> bool {
+        self.status == PlayerStatus::GameOver && self.continue_timeout > 0.0
+    }
+
+    fn is_invulnerable(&self) -> bool {
+        self.invulnerability_timeout > 0.0
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        self.scene_object.write(writer);
+        self.hitbox_scene_object.write(writer);
+        writer.write_point(self.spawn_point);
+        writer.write_u8(match self.status {
+                            PlayerStatus::Alive => 0,
+                            PlayerStatus::Dead => 1,
+                            PlayerStatus::GameOver => 2,
+                        });
+        writer.write_bool(self.focused);
+        writer.write_u32(self.weapon_level);
+        writer.write_u32(self.lives);
+        writer.write_u32(self.bombs);
+        writer.write_bool(self.bomb_was_pressed);
+        writer.write_bool(self.fire_was_pressed);
+        writer.write_f32(self.respawn_timeout);
+        writer.write_f32(self.invulnerability_timeout);
+        writer.write_f32(self.continue_timeout);
+        writer.write_f32(self.firing_timeout);
+        writer.write_u64(self.score);
+        writer.write_u32(self.chain);
+        writer.write_f32(self.chain_timeout);
+        writer.write_u32(self.grazes);
+        writer.write_u32(self.continues);
+    }
+
+    fn read(reader: &mut Reader, context: &SnapshotContext) -> Result<Player, String> {
+        Ok(Player {
+               scene_object: SceneObject::read(reader, context)?,
+               hitbox_scene_object: SceneObject::read(reader, context)?,
+               spawn_point: reader.read_point()?,
+               status: match reader.read_u8()? {
+                   0 => PlayerStatus::Alive,
+                   1 => PlayerStatus::Dead,
+                   2 => PlayerStatus::GameOver,
+                   status => return Err(format!("Can't parse player status {}", status)),
+               },
+               focused: reader.read_bool()?,
+               weapon_level: reader.read_u32()?,
+               lives: reader.read_u32()?,
+               bombs: reader.read_u32()?,
+               bomb_was_pressed: reader.read_bool()?,
+               fire_was_pressed: reader.read_bool()?,
+               respawn_timeout: reader.read_f32()?,
+               invulnerability_timeout: reader.read_f32()?,
+               continue_timeout: reader.read_f32()?,
+               firing_timeout: reader.read_f32()?,
+               score: reader.read_u64()?,
+               chain: reader.read_u32()?,
+               chain_timeout: reader.read_f32()?,
+               grazes: reader.read_u32()?,
+               continues: reader.read_u32()?,
+           })
+    }
+
+    fn is_visible(&self, blinking_speed: Speed) -> bool {
+        self.status == PlayerStatus::Alive &&
+        (!self.is_invulnerable() ||
+         (self.invulnerability_timeout * blinking_speed) as u32 % 2 == 0)
+    }
+}
+
+// What the indices decoded from a snapshot are checked against.
+struct SnapshotContext<'a, 'b: 'a> {
+    sprite_data_cache: &'a SpriteDataCache<'b>,
+    stage: &'a Stage,
+    bulletml_library: &'a bulletml::Library,
+    players: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BossState {
+    boss: usize,
+    phase: usize,
+    phase_time: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneState {
+    background_position: f32,
+    prev_background_position: f32,
+    players: Vec<Player>,
+    bomb_timeout: f32,
+    bomb_pos: FPoint,
+    bullets_frame: f32,
+    player_bullets: Vec<SceneObject>,
+    enemy_bullets: Vec<SceneObject>,
+    enemies: Vec<SceneObject>,
+    explosions: Vec<SceneObject>,
+    items: Vec<SceneObject>,
+    stage_time: f32,
+    stage_start_position: f32,
+    next_timed_spawn: usize,
+    next_scrolled_spawn: usize,
+    boss: Option<BossState>,
+    rank: f32,
+    rng: Rng,
+}
+
+impl SceneState {
+    fn write_objects(writer: &mut Writer, objects: &[SceneObject]) {
+        writer.write_usize(objects.len());
+        for object in objects {
+            object.write(writer);
+        }
+    }
+
+    fn read_objects(reader: &mut Reader,
+                    context: &SnapshotContext)
+                    -> Result<Vec<SceneObject>, String> {
+        let mut objects = vec![];
+        for _ in 0..reader.read_usize()? {
+            objects.push(SceneObject::read(reader, context)?);
+        }
+        Ok(objects)
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.write_f32(self.background_position);
+        writer.write_f32(self.prev_background_position);
+        writer.write_usize(self.players.len());
+        for player in &self.players {
+            player.write(writer);
+        }
+        writer.write_f32(self.bomb_timeout);
+        writer.write_point(self.bomb_pos);
+        writer.write_f32(self.bullets_frame);
+        SceneState::write_objects(writer, &self.player_bullets);
+        SceneState::write_objects(writer, &self.enemy_bullets);
+        SceneState::write_objects(writer, &self.enemies);
+        SceneState::write_objects(writer, &self.explosions);
+        SceneState::write_objects(writer, &self.items);
+        writer.write_f32(self.stage_time);
+        writer.write_f32(self.stage_start_position);
+        writer.write_usize(self.next_timed_spawn);
+        writer.write_usize(self.next_scrolled_spawn);
+        writer.write_bool(self.boss.is_some());
+        if let Some(boss) = self.boss {
+            writer.write_usize(boss.boss);
+            writer.write_usize(boss.phase);
+            writer.write_f32(boss.phase_time);
+        }
+        writer.write_f32(self.rank);
+        writer.write_u64(self.rng.state());
+    }
+
+    fn read(reader: &mut Reader,
+            sprite_data_cache: &SpriteDataCache,
+            stage: &Stage,
+            bulletml_library: &bulletml::Library)
+            -> Result<SceneState, String> {
+        let background_position = reader.read_f32()?;
+        let prev_background_position = reader.read_f32()?;
+        let context = SnapshotContext {
+            sprite_data_cache: sprite_data_cache,
+            stage: stage,
+            bulletml_library: bulletml_library,
+            players: reader.read_usize()?,
+        };
+        let mut players = vec![];
+        for _ in 0..context.players {
+            players.push(Player::read(reader, &context)?);
+        }
+        Ok(SceneState {
+               background_position: background_position,
+               prev_background_position: prev_background_position,
+               players: players,
+               bomb_timeout: reader.read_f32()?,
+               bomb_pos: reader.read_point()?,
+               bullets_frame: reader.read_f32()?,
+               player_bullets: SceneState::read_objects(reader, &context)?,
+               enemy_bullets: SceneState::read_objects(reader, &context)?,
+               enemies: SceneState::read_objects(reader, &context)?,
+               explosions: SceneState::read_objects(reader, &context)?,
+               items: SceneState::read_objects(reader, &context)?,
+               stage_time: reader.read_f32()?,
+               stage_start_position: reader.read_f32()?,
+               next_timed_spawn: reader.read_usize()?,
+               next_scrolled_spawn: reader.read_usize()?,
+               boss: if reader.read_bool()? {
+                   let boss = reader.read_index(stage.bosses().len(), "boss")?;
+                   Some(BossState {
+                            boss: boss,
+                            phase: reader.read_index(stage.boss(boss).phases.len(), "boss phase")?,
+                            phase_time: reader.read_f32()?,
+                        })
+               } else {
+                   None
+               },
+               rank: reader.read_f32()?,
+               rng: Rng::from_state(reader.read_u64()?)
+                   .ok_or_else(|| "Can't restore RNG state".to_string())?,
+           })
+    }
 }
 
 #[derive(Debug)]
@@ -96,9 +600,22 @@ pub struct SpeedValues {
     background_speed: Speed,
     x_speed: Speed,
     y_speed: Speed,
+    focused_x_speed: Speed,
+    focused_y_speed: Speed,
     bullet_blicking_speed: Speed,
     bullet_speed: Speed,
     bullet_shooting_speed: Speed,
+    explosion_speed: Speed,
+    respawn_duration: f32,
+    invulnerability_duration: f32,
+    invulnerability_blinking_speed: Speed,
+    continue_duration: f32,
+    chain_duration: f32,
+    item_speed: Speed,
+    item_falling_speed: Speed,
+    item_gravity: Acceleration,
+    bomb_duration: f32,
+    bomb_radius: CoordValue,
 }
 
 impl Default for SpeedValues {
@@ -106,19 +623,36 @@ impl Default for SpeedValues {
         SpeedValues {
             x_speed: 0.75,
             y_speed: 0.75,
+            focused_x_speed: 0.3,
+            focused_y_speed: 0.3,
             background_speed: 0.15,
             bullet_blicking_speed: 2.0,
             bullet_speed: 0.5,
             bullet_shooting_speed: 0.2,
+            explosion_speed: 12.0,
+            respawn_duration: 1.5,
+            invulnerability_duration: 3.0,
+            invulnerability_blinking_speed: 10.0,
+            continue_duration: 10.0,
+            chain_duration: 2.0,
+            item_speed: 0.3,
+            item_falling_speed: 0.35,
+            item_gravity: 0.8,
+            bomb_duration: 1.5,
+            bomb_radius: 0.4,
         }
     }
 }
 
 pub struct SceneIterator<'a> {
+    enemies: <&'a Vec<SceneObject> as IntoIterator>::IntoIter,
+    explosions: <&'a Vec<SceneObject> as IntoIterator>::IntoIter,
     player_bullets: <&'a Vec<SceneObject> as IntoIterator>::IntoIter,
     enemy_bullets: <&'a Vec<SceneObject> as IntoIterator>::IntoIter,
-    player: &'a SceneObject,
-    empty: bool,
+    items: <&'a Vec<SceneObject> as IntoIterator>::IntoIter,
+    players: <&'a Vec<Player> as IntoIterator>::IntoIter,
+    hitbox: Option<&'a SceneObject>,
+    blinking_speed: Speed,
 }
 
 impl<'a> SceneIterator<'a> {
@@ -126,10 +660,32 @@ impl<'a> SceneIterator<'a> {
         where 'b: 'a
     {
         SceneIterator {
+            enemies: scene.enemies.iter(),
+            explosions: scene.explosions.iter(),
             player_bullets: scene.player_bullets.iter(),
             enemy_bullets: scene.enemy_bullets.iter(),
-            player: &scene.player_scene_object,
-            empty: false,
+            items: scene.items.iter(),
+            players: scene.players.iter(),
+            hitbox: None,
+            blinking_speed: scene.speeds.invulnerability_blinking_speed,
+        }
+    }
+
+    fn next_player_object(&mut self) -> Option<&'a SceneObject> {
+        loop {
+            if let Some(hitbox) = self.hitbox.take() {
+                return Some(hitbox);
+            }
+            let player = match self.players.next() {
+                Some(player) => player,
+                None => return None,
+            };
+            if player.status == PlayerStatus::Alive && player.focused {
+                self.hitbox = Some(&player.hitbox_scene_object);
+            }
+            if player.is_visible(self.blinking_speed) {
+                return Some(&player.scene_object);
+            }
         }
     }
 }
@@ -138,20 +694,13 @@ impl<'a> Iterator for SceneIterator<'a> {
     type Item = &'a SceneObject;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.empty {
-            None
-        } else {
-            self.player_bullets
-                .next()
-                .or_else(|| {
-                             self.enemy_bullets
-                                 .next()
-                                 .or_else(|| {
-                                              self.empty = true;
-                                              Some(self.player)
-                                          })
-                         })
-        }
+        self.enemies
+            .next()
+            .or_else(|| self.explosions.next())
+            .or_else(|| self.items.next())
+            .or_else(|| self.player_bullets.next())
+            .or_else(|| self.enemy_bullets.next())
+            .or_else(|| self.next_player_object())
     }
 }
 
@@ -160,6 +709,12 @@ pub struct SpriteDataCache<'a> {
     player_sprite_data: &'a SpriteData,
     player_bullet_sprite_data: &'a SpriteData,
     enemy_bullet_sprite_data: &'a SpriteData,
+    enemy_sprite_data: &'a SpriteData,
+    boss_sprite_data: &'a SpriteData,
+    explosion_sprite_data: &'a SpriteData,
+    score_item_sprite_data: &'a SpriteData,
+    power_item_sprite_data: &'a SpriteData,
+    hitbox_sprite_data: &'a SpriteData,
 }
 
 impl<'a> SpriteDataCache<'a> {
@@ -172,6 +727,12 @@ impl<'a> SpriteDataCache<'a> {
             enemy_bullet_sprite_data: sprites_data
                 .sprite_data(SpriteObject::EnemyBullet)
                 .unwrap(),
+            enemy_sprite_data: sprites_data.sprite_data(SpriteObject::Enemy).unwrap(),
+            explosion_sprite_data: sprites_data.sprite_data(SpriteObject::Explosion).unwrap(),
+            score_item_sprite_data: sprites_data.sprite_data(SpriteObject::ScoreItem).unwrap(),
+            power_item_sprite_data: sprites_data.sprite_data(SpriteObject::PowerItem).unwrap(),
+            boss_sprite_data: sprites_data.sprite_data(SpriteObject::Boss).unwrap(),
+            hitbox_sprite_data: sprites_data.sprite_data(SpriteObject::Hitbox).unwrap(),
         }
     }
 
@@ -180,6 +741,12 @@ impl<'a> SpriteDataCache<'a> {
             ObjectType::Player(..) => self.player_sprite_data,
             ObjectType::EnemyBullet(..) => self.enemy_bullet_sprite_data,
             ObjectType::PlayerBullet(..) => self.player_bullet_sprite_data,
+            ObjectType::Enemy => self.enemy_sprite_data,
+            ObjectType::Explosion(..) => self.explosion_sprite_data,
+            ObjectType::ScoreItem(..) => self.score_item_sprite_data,
+            ObjectType::PowerItem(..) => self.power_item_sprite_data,
+            ObjectType::Hitbox => self.hitbox_sprite_data,
+            ObjectType::Boss => self.boss_sprite_data,
         }
     }
 }
@@ -189,145 +756,699 @@ pub struct Scene<'a> {
     sprites_data: &'a SpritesData,
     speeds: SpeedValues,
     background_position: f32,
-    player_scene_object: SceneObject,
+    prev_background_position: f32,
+    players: Vec<Player>,
+    lives_mode: LivesMode,
+    weapon: Weapon,
+    bomb_timeout: f32,
+    bomb_pos: FPoint,
+    bomb_converts_bullets: bool,
     bullets_frame: f32,
-    firing_timeout: f32,
     player_bullets: Vec<SceneObject>,
     enemy_bullets: Vec<SceneObject>,
-    new_bullet_timeout: f32,
+    enemies: Vec<SceneObject>,
+    explosions: Vec<SceneObject>,
+    items: Vec<SceneObject>,
+    graze_distance: f32,
+    stage: Stage,
+    stage_time: f32,
+    stage_start_position: f32,
+    next_timed_spawn: usize,
+    next_scrolled_spawn: usize,
+    boss: Option<BossState>,
+    bulletml_library: bulletml::Library,
+    rank: f32,
+    seed: u64,
+    rng: Rng,
     sprite_data_cache: SpriteDataCache<'a>,
     collision_detector: CollisionDetector,
 }
 
 impl<'a> Scene<'a> {
     pub fn new(sprites_data: &'a SpritesData) -> Scene<'a> {
+        Scene::with_seed(sprites_data, DEFAULT_SEED)
+    }
+
+    pub fn with_seed(sprites_data: &'a SpritesData, seed: u64) -> Scene<'a> {
+        Scene::with_players(sprites_data, seed, 1)
+    }
+
+    pub fn with_players(sprites_data: &'a SpritesData, seed: u64, players: usize) -> Scene<'a> {
         let speeds = SpeedValues::default();
         let bullets_timeout = speeds.bullet_shooting_speed + 1.0;
         let sprite_data_cache = SpriteDataCache::new(sprites_data);
-        let player_scene_object = SceneObject::new(&sprite_data_cache,
-                                                   ObjectType::Player(PlayerState::Normal),
-                                                   (0.5, 0.8),
-                                                   Angle::from_deg(0.0),
-                                                   (1.0, 1.0));
+        let bulletml_library = bulletml::Library::new();
+        let stage = Stage::new(&bulletml_library);
+        let players = (0..players)
+            .map(|i| {
+                let x = 0.5 + (i as CoordValue - (players - 1) as CoordValue / 2.0) *
+                              PLAYER_SPAWN_SPACING;
+                Player::new(&sprite_data_cache,
+                            FPoint::new(x, PLAYER_SPAWN_Y),
+                            bullets_timeout)
+            })
+            .collect();
         Scene {
             speeds: speeds,
             background_position: 0.0,
-            player_scene_object: player_scene_object,
+            prev_background_position: 0.0,
+            players: players,
+            lives_mode: LivesMode::Separate,
+            weapon: Weapon::new(),
+            bomb_timeout: 0.0,
+            bomb_pos: FPoint::new(0.5, PLAYER_SPAWN_Y),
+            bomb_converts_bullets: true,
             bullets_frame: 0.0,
             sprites_data: sprites_data,
-            firing_timeout: bullets_timeout,
             player_bullets: vec![],
             enemy_bullets: vec![],
-            new_bullet_timeout: bullets_timeout,
+            enemies: vec![],
+            explosions: vec![],
+            items: vec![],
+            graze_distance: GRAZE_DISTANCE / sprites_data.virtual_dimensions().x() as f32,
+            stage: stage,
+            stage_time: 0.0,
+            stage_start_position: 0.0,
+            next_timed_spawn: 0,
+            next_scrolled_spawn: 0,
+            boss: None,
+            bulletml_library: bulletml_library,
+            rank: DEFAULT_RANK,
+            seed: seed,
+            rng: Rng::new(seed),
             sprite_data_cache: sprite_data_cache,
             collision_detector: CollisionDetector::new(sprites_data.virtual_dimensions()),
         }
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn total_objects(&self) -> usize {
-        self.player_bullets.len() + self.enemy_bullets.len() + 1
+        self.player_bullets.len() + self.enemy_bullets.len() + self.enemies.len() +
+        self.explosions.len() + self.items.len() +
+        self.players
+            .iter()
+            .filter(|player| player.status == PlayerStatus::Alive)
+            .count()
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    pub fn bomb_effect(&self) -> Option<(FPoint, f32)> {
+        if self.bomb_timeout > 0.0 {
+            Some((self.bomb_pos, 1.0 - self.bomb_timeout / self.speeds.bomb_duration))
+        } else {
+            None
+        }
+    }
+
+    pub fn max_weapon_level(&self) -> u32 {
+        self.weapon.max_level()
+    }
+
+    pub fn boss_health(&self) -> Option<f32> {
+        let state = match self.boss {
+            Some(state) => state,
+            None => return None,
+        };
+        let phase = &self.stage.boss(state.boss).phases[state.phase];
+        self.enemies
+            .iter()
+            .find(|enemy| enemy.object_type == ObjectType::Boss)
+            .map(|boss| boss.health as f32 / phase.health.max(1) as f32)
+    }
+
+    pub fn score(&self) -> u64 {
+        self.players.iter().map(|player| player.score).sum()
+    }
+
+    pub fn game_over(&self) -> bool {
+        self.players
+            .iter()
+            .all(|player| player.status == PlayerStatus::GameOver && !player.can_continue())
+    }
+
+    pub fn set_lives_mode(&mut self, lives_mode: LivesMode) {
+        self.lives_mode = lives_mode;
+    }
+
+    pub fn set_invulnerability_duration(&mut self, duration_s: f32) {
+        self.speeds.invulnerability_duration = duration_s;
+    }
+
+    pub fn set_continue_duration(&mut self, duration_s: f32) {
+        self.speeds.continue_duration = duration_s;
+    }
+
+    pub fn set_graze_distance(&mut self, distance: f32) {
+        self.graze_distance = distance;
+    }
+
+    pub fn set_bomb_converts_bullets(&mut self, converts: bool) {
+        self.bomb_converts_bullets = converts;
     }
 
     pub fn background_position(&self) -> f32 {
         self.background_position
     }
 
-    pub fn tick(&mut self, input: &InputPoller, duration: Duration) {
-        let duration_s = (duration.as_secs() as f32) +
-                         (duration.subsec_nanos() as f32 / 1_000_000_000f32);
-        self.process_input(input, duration_s);
-        self.move_player(input, duration_s);
+    pub fn interpolated_background_position(&self, alpha: f32) -> f32 {
+        self.prev_background_position +
+        (self.background_position - self.prev_background_position) * alpha
+    }
+
+    // Players without an input get the default one, so their timers keep running.
+    pub fn tick<C>(&mut self, inputs: &[C], duration: Duration)
+        where C: Controls + Default
+    {
+        let duration_s = seconds(duration);
+        self.save_positions();
+        let default_input = C::default();
+        for player in 0..self.players.len() {
+            let input = inputs.get(player).unwrap_or(&default_input);
+            self.update_continue(player, input, duration_s);
+            self.update_player_status(player, duration_s);
+            if self.players[player].status == PlayerStatus::Alive {
+                self.players[player].focused = input.focus_is_pressed();
+                self.process_input(player, input, duration_s);
+                self.move_player(player, input, duration_s);
+            }
+        }
+        self.update_bomb(duration_s);
         self.move_background(duration_s);
-        self.add_bullets(duration_s);
+        self.spawn_enemies(duration_s);
+        self.fire_enemy_bullets(duration_s);
+        self.run_bulletml(duration_s);
         self.move_bullets(duration_s);
+        self.move_enemies(duration_s);
+        self.move_items(duration_s);
         self.detect_collisions();
+        self.update_boss(duration_s);
+        self.destroy_players();
+        self.destroy_enemies();
+        self.update_chain(duration_s);
         self.blink_bullet(duration_s);
+        self.animate_explosions(duration_s);
     }
 
     pub fn objects(&self) -> SceneIterator {
         SceneIterator::new(self)
     }
 
-    fn add_bullets(&mut self, duration_s: f32) {
-        self.new_bullet_timeout += duration_s;
-        if self.new_bullet_timeout >= self.speeds.bullet_shooting_speed * 2.0 {
-            self.new_bullet_timeout = 0.0;
-            self.enemy_bullets
-                .push(SceneObject::new(&self.sprite_data_cache,
-                                       ObjectType::EnemyBullet(0),
-                                       (self.player_scene_object.pos.x(), MIN_Y_VALUE),
-                                       Angle::from_deg(-180.0),
-                                       (2.0, 2.0)));
-            // let bullets_count = 3200;
-            // for x in 0..bullets_count {
-            //     self.enemy_bullets.push(SceneObject::new(&self.sprite_data_cache,
-            //                                              ObjectType::EnemyBullet(0),
-            //                                              (x as f32 / bullets_count as f32,
-            //                                               MAX_Y_VALUE),
-            //                                              Angle::from_deg(-180.0)));
-            //     self.player_bullets.push(SceneObject::new(&self.sprite_data_cache,
-            //                                               ObjectType::PlayerBullet(0),
-            //                                               (x as f32 / bullets_count as f32,
-            //                                                MIN_Y_VALUE),
-            //                                               Angle::from_deg(0.0)));
-            // }
-        }
-    }
-
-    fn process_input(&mut self, input: &InputPoller, duration_s: f32) {
-        self.firing_timeout += duration_s;
-        if input.fire_is_pressed() && self.firing_timeout >= self.speeds.bullet_shooting_speed {
-            self.firing_timeout = 0.0;
-            let adjusted_angle = self.player_scene_object.sprite_angle();
-            self.player_bullets
-                .push(SceneObject::new(&self.sprite_data_cache,
-                                       ObjectType::PlayerBullet(0),
-                                       self.player_scene_object.pos,
-                                       adjusted_angle,
-                                       (2.0, 2.0)));
-            self.player_bullets
-                .push(SceneObject::new(&self.sprite_data_cache,
-                                       ObjectType::PlayerBullet(0),
-                                       self.player_scene_object.pos,
-                                       adjusted_angle.add_deg(20.0),
-                                       (1.0, 1.0)));
-            self.player_bullets
-                .push(SceneObject::new(&self.sprite_data_cache,
-                                       ObjectType::PlayerBullet(0),
-                                       self.player_scene_object.pos,
-                                       adjusted_angle.add_deg(-20.0),
-                                       (1.0, 1.0)));
+    pub fn save_state(&self) -> SceneState {
+        SceneState {
+            background_position: self.background_position,
+            prev_background_position: self.prev_background_position,
+            players: self.players.clone(),
+            bomb_timeout: self.bomb_timeout,
+            bomb_pos: self.bomb_pos,
+            bullets_frame: self.bullets_frame,
+            player_bullets: self.player_bullets.clone(),
+            enemy_bullets: self.enemy_bullets.clone(),
+            enemies: self.enemies.clone(),
+            explosions: self.explosions.clone(),
+            items: self.items.clone(),
+            stage_time: self.stage_time,
+            stage_start_position: self.stage_start_position,
+            next_timed_spawn: self.next_timed_spawn,
+            next_scrolled_spawn: self.next_scrolled_spawn,
+            boss: self.boss,
+            rank: self.rank,
+            rng: self.rng,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &SceneState) {
+        self.background_position = state.background_position;
+        self.prev_background_position = state.prev_background_position;
+        self.players = state.players.clone();
+        self.bomb_timeout = state.bomb_timeout;
+        self.bomb_pos = state.bomb_pos;
+        self.bullets_frame = state.bullets_frame;
+        self.player_bullets = state.player_bullets.clone();
+        self.enemy_bullets = state.enemy_bullets.clone();
+        self.enemies = state.enemies.clone();
+        self.explosions = state.explosions.clone();
+        self.items = state.items.clone();
+        self.stage_time = state.stage_time;
+        self.stage_start_position = state.stage_start_position;
+        self.next_timed_spawn = state.next_timed_spawn;
+        self.next_scrolled_spawn = state.next_scrolled_spawn;
+        self.boss = state.boss;
+        self.rank = state.rank;
+        self.rng = state.rng;
+    }
+
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.write_bytes(SNAPSHOT_MAGIC);
+        writer.write_u32(SNAPSHOT_VERSION);
+        self.save_state().write(&mut writer);
+        writer.into_bytes()
+    }
+
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = Reader::new(bytes);
+        if reader.read_bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err("Not a scene snapshot".to_string());
+        }
+        let version = reader.read_u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("Unsupported snapshot version {}", version));
+        }
+        let state = SceneState::read(&mut reader,
+                                     &self.sprite_data_cache,
+                                     &self.stage,
+                                     &self.bulletml_library)?;
+        reader.finish()?;
+        if state.players.len() != self.players.len() {
+            return Err(format!("Snapshot has {} players instead of {}",
+                               state.players.len(),
+                               self.players.len()));
+        }
+        self.load_state(&state);
+        Ok(())
+    }
+
+    // FNV-1a over the simulation state, used to compare runs that should be identical.
+    pub fn checksum(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut rng = self.rng;
+        {
+            let mut add = |value: u64| {
+                hash ^= value;
+                hash = hash.wrapping_mul(0x100_0000_01b3);
+            };
+            add(self.background_position.to_bits() as u64);
+            add(self.stage_time.to_bits() as u64);
+            add(rng.next_u32() as u64);
+            for player in &self.players {
+                add(player.score);
+                add(player.lives as u64);
+                add(player.weapon_level as u64);
+                add(player.scene_object.pos.x().to_bits() as u64);
+                add(player.scene_object.pos.y().to_bits() as u64);
+            }
+            let objects = self.player_bullets
+                .iter()
+                .chain(self.enemy_bullets.iter())
+                .chain(self.enemies.iter())
+                .chain(self.items.iter());
+            for object in objects {
+                add(object.pos.x().to_bits() as u64);
+                add(object.pos.y().to_bits() as u64);
+                add(object.health as u64);
+            }
         }
+        hash
     }
 
-    fn move_player(&mut self, input: &InputPoller, duration_s: f32) {
-        let (mut x, mut y) = self.player_scene_object.pos.into();
+    fn save_positions(&mut self) {
+        use std::iter;
+
+        self.prev_background_position = self.background_position;
+        let objects = self.player_bullets
+            .iter_mut()
+            .chain(self.enemy_bullets.iter_mut())
+            .chain(self.enemies.iter_mut())
+            .chain(self.explosions.iter_mut())
+            .chain(self.items.iter_mut())
+            .chain(self.players
+                       .iter_mut()
+                       .flat_map(|player| {
+                                     iter::once(&mut player.scene_object)
+                                         .chain(iter::once(&mut player.hitbox_scene_object))
+                                 }));
+        for object in objects {
+            object.prev_pos = object.pos;
+        }
+    }
+
+    fn update_player_status(&mut self, player: usize, duration_s: f32) {
+        let player = &mut self.players[player];
+        player.invulnerability_timeout = (player.invulnerability_timeout - duration_s).max(0.0);
+        if player.status != PlayerStatus::Dead {
+            return;
+        }
+        player.respawn_timeout -= duration_s;
+        if player.respawn_timeout <= 0.0 {
+            player.scene_object.pos = player.spawn_point;
+            player.scene_object.prev_pos = player.scene_object.pos;
+            player.status = PlayerStatus::Alive;
+            player.invulnerability_timeout = self.speeds.invulnerability_duration;
+        }
+    }
+
+    fn update_continue<C>(&mut self, player: usize, input: &C, duration_s: f32)
+        where C: Controls
+    {
+        let continued = {
+            let player = &mut self.players[player];
+            let fire_was_pressed = player.fire_was_pressed;
+            player.fire_was_pressed = input.fire_is_pressed();
+            if !player.can_continue() {
+                return;
+            }
+            player.continue_timeout = (player.continue_timeout - duration_s).max(0.0);
+            input.fire_is_pressed() && !fire_was_pressed
+        };
+        if continued {
+            self.continue_game(player);
+        }
+    }
+
+    // Shared lives are restored for the whole team, otherwise only for the continuing player.
+    fn continue_game(&mut self, continuing: usize) {
+        let lives_mode = self.lives_mode;
+        for (i, player) in self.players.iter_mut().enumerate() {
+            if lives_mode == LivesMode::Separate && i != continuing {
+                continue;
+            }
+            player.lives = STARTING_LIVES;
+            if player.status == PlayerStatus::GameOver {
+                player.status = PlayerStatus::Dead;
+                player.respawn_timeout = 0.0;
+                player.continue_timeout = 0.0;
+                player.bombs = STARTING_BOMBS;
+                player.score = 0;
+                player.chain = 0;
+                player.continues += 1;
+            }
+        }
+    }
+
+    fn target_pos(players: &[Player], pos: FPoint) -> FPoint {
+        let distance = |player: &Player| {
+            let delta = player.scene_object.pos - pos;
+            delta.x().hypot(delta.y())
+        };
+        players
+            .iter()
+            .filter(|player| player.status == PlayerStatus::Alive)
+            .fold(None, |nearest: Option<&Player>, player| match nearest {
+                Some(nearest) if distance(nearest) <= distance(player) => Some(nearest),
+                _ => Some(player),
+            })
+            .or_else(|| players.first())
+            .map_or(FPoint::new(0.5, PLAYER_SPAWN_Y), |player| player.scene_object.pos)
+    }
+
+    fn spawn_enemies(&mut self, duration_s: f32) {
+        if self.boss.is_none() {
+            self.stage_time += duration_s;
+        }
+        if let Some(length) = self.stage.length() {
+            if self.stage_time >= length {
+                self.stage_time -= length;
+                self.stage_start_position = self.background_position;
+                self.next_timed_spawn = 0;
+                self.next_scrolled_spawn = 0;
+            }
+        }
+
+        while let Some(spawn) = self.stage.timed_spawns().get(self.next_timed_spawn) {
+            if spawn.at > self.stage_time {
+                break;
+            }
+            Scene::spawn_enemy(&mut self.enemies,
+                               &self.sprite_data_cache,
+                               &self.bulletml_library,
+                               &self.stage,
+                               spawn);
+            if let Some(boss) = spawn.boss {
+                self.boss = Some(BossState {
+                                     boss: boss,
+                                     phase: 0,
+                                     phase_time: 0.0,
+                                 });
+            }
+            self.next_timed_spawn += 1;
+        }
+
+        let stage_position = self.background_position - self.stage_start_position;
+        while let Some(spawn) = self.stage.scrolled_spawns().get(self.next_scrolled_spawn) {
+            if spawn.at > stage_position {
+                break;
+            }
+            Scene::spawn_enemy(&mut self.enemies,
+                               &self.sprite_data_cache,
+                               &self.bulletml_library,
+                               &self.stage,
+                               spawn);
+            self.next_scrolled_spawn += 1;
+        }
+    }
+
+    fn spawn_enemy(enemies: &mut Vec<SceneObject>,
+                   sprite_data_cache: &SpriteDataCache,
+                   bulletml_library: &bulletml::Library,
+                   stage: &Stage,
+                   spawn: &Spawn) {
+        let enemy_kind = stage.enemy_kind(spawn.behavior.enemy_kind);
+        let object_type = if spawn.boss.is_some() {
+            ObjectType::Boss
+        } else {
+            ObjectType::Enemy
+        };
+        let mut enemy = SceneObject::new(sprite_data_cache,
+                                         object_type,
+                                         spawn.pos,
+                                         Angle::from_deg(180.0),
+                                         (enemy_kind.scale, enemy_kind.scale));
+        enemy.health = match spawn.boss {
+            Some(boss) => stage.boss(boss).phases[0].health,
+            None => enemy_kind.health,
+        };
+        Scene::set_behavior(&mut enemy, bulletml_library, stage, spawn.behavior);
+        enemies.push(enemy);
+    }
+
+    fn set_behavior(enemy: &mut SceneObject,
+                    bulletml_library: &bulletml::Library,
+                    stage: &Stage,
+                    behavior: Behavior) {
+        enemy.behavior = Some(behavior);
+        enemy.lifetime = 0.0;
+        enemy.firing_timeout = 0.0;
+        enemy.bulletml = match stage.pattern(behavior.pattern).kind {
+            PatternKind::BulletML(document) => {
+                Some(bulletml::Runner::new(bulletml_library, document))
+            }
+            _ => None,
+        };
+    }
+
+    fn fire_enemy_bullets(&mut self, duration_s: f32) {
+        for enemy in &mut self.enemies {
+            let pattern = match enemy.behavior {
+                Some(behavior) => self.stage.pattern(behavior.pattern),
+                None => continue,
+            };
+            enemy.firing_timeout += duration_s;
+            if enemy.firing_timeout < pattern.interval {
+                continue;
+            }
+            enemy.firing_timeout = 0.0;
+
+            let (first_angle, step) = match pattern.kind {
+                PatternKind::None |
+                PatternKind::BulletML(..) => continue,
+                PatternKind::Aimed | PatternKind::Fixed => {
+                    let direction = if pattern.kind == PatternKind::Aimed {
+                        let delta = Scene::target_pos(&self.players, enemy.pos) - enemy.pos;
+                        Angle::from_rad(delta.y().atan2(delta.x())).as_deg()
+                    } else {
+                        90.0
+                    };
+                    if pattern.count > 1 {
+                        (direction - pattern.spread / 2.0,
+                         pattern.spread / (pattern.count - 1) as f32)
+                    } else {
+                        (direction, 0.0)
+                    }
+                }
+                PatternKind::Ring => {
+                    let volley = (enemy.lifetime / pattern.interval) as u32;
+                    (volley as f32 * pattern.spread, 360.0 / pattern.count as f32)
+                }
+            };
+
+            for i in 0..pattern.count {
+                let direction = Angle::from_deg(first_angle + step * i as f32);
+                let mut bullet = SceneObject::new(&self.sprite_data_cache,
+                                                  ObjectType::EnemyBullet(0),
+                                                  enemy.pos,
+                                                  direction.add_deg(90.0),
+                                                  (1.0, 1.0));
+                bullet.speed = pattern.speed.unwrap_or(self.speeds.bullet_speed);
+                bullet.acceleration = pattern.acceleration;
+                bullet.angular_velocity = pattern.angular_velocity;
+                bullet.min_speed = pattern.min_speed;
+                bullet.max_speed = pattern.max_speed;
+                self.enemy_bullets.push(bullet);
+            }
+        }
+    }
+
+    fn run_bulletml(&mut self, duration_s: f32) {
+        let mut fired = vec![];
+        {
+            let mut context = bulletml::Context {
+                target: FPoint::new(0.5, PLAYER_SPAWN_Y),
+                rank: self.rank,
+                rng: &mut self.rng,
+            };
+            for object in self.enemies
+                    .iter_mut()
+                    .chain(self.enemy_bullets.iter_mut()) {
+                if let Some(mut runner) = object.bulletml.take() {
+                    context.target = Scene::target_pos(&self.players, object.pos);
+                    runner.update(&self.bulletml_library,
+                                  object,
+                                  duration_s,
+                                  &mut context,
+                                  &mut fired);
+                    if !runner.is_finished() {
+                        object.bulletml = Some(runner);
+                    }
+                }
+            }
+        }
+        for fired in fired {
+            let mut bullet = SceneObject::new(&self.sprite_data_cache,
+                                              ObjectType::EnemyBullet(0),
+                                              fired.pos,
+                                              fired.direction,
+                                              (1.0, 1.0));
+            bullet.speed = fired.speed;
+            bullet.bulletml = fired.runner;
+            self.enemy_bullets.push(bullet);
+        }
+        self.enemy_bullets.retain(|bullet| !bullet.to_delete);
+    }
+
+    fn process_input<C>(&mut self, player: usize, input: &C, duration_s: f32)
+        where C: Controls
+    {
+        // A press is only used up by a bomb going off, so pressing during another player's bomb
+        // drops one as soon as that bomb ends.
+        let bomb_dropped = input.bomb_is_pressed() && !self.players[player].bomb_was_pressed &&
+                           self.players[player].bombs > 0 &&
+                           self.bomb_timeout <= 0.0;
+        if bomb_dropped {
+            self.drop_bomb(player);
+        }
+
+        let player_index = player;
+        let player = &mut self.players[player];
+        player.bomb_was_pressed = input.bomb_is_pressed() &&
+                                  (player.bomb_was_pressed || bomb_dropped);
+        player.firing_timeout += duration_s;
+        if input.fire_is_pressed() && player.firing_timeout >= self.speeds.bullet_shooting_speed {
+            player.firing_timeout = 0.0;
+            let adjusted_angle = player.scene_object.sprite_angle();
+            let spread = if player.focused { FOCUSED_SPREAD } else { 1.0 };
+            for shot in self.weapon.shots(player.weapon_level) {
+                let mut bullet = SceneObject::new(&self.sprite_data_cache,
+                                                  ObjectType::PlayerBullet(0),
+                                                  player.scene_object.pos,
+                                                  adjusted_angle.add_deg(shot.angle * spread),
+                                                  (shot.scale, shot.scale));
+                bullet.speed = self.speeds.bullet_speed;
+                bullet.damage = shot.damage;
+                bullet.owner = player_index;
+                self.player_bullets.push(bullet);
+            }
+        }
+    }
+
+    fn move_player<C>(&mut self, player: usize, input: &C, duration_s: f32)
+        where C: Controls
+    {
+        let player = &mut self.players[player];
+        let (mut x, mut y) = player.scene_object.pos.into();
         let x_move = input.x_move();
+        let (x_speed, y_speed) = if player.focused {
+            (self.speeds.focused_x_speed, self.speeds.focused_y_speed)
+        } else {
+            (self.speeds.x_speed, self.speeds.y_speed)
+        };
         {
-            let player_virtual_hitbox = self.player_scene_object.collision_data().hitbox();
-            x += x_move * self.speeds.x_speed * (duration_s as CoordValue);
+            let player_virtual_hitbox = player.scene_object.collision_data().hitbox();
+            x += x_move * x_speed * (duration_s as CoordValue);
             x = x.min(MAX_X_VALUE - player_virtual_hitbox.right)
                 .max(MIN_X_VALUE + player_virtual_hitbox.left);
-            y += input.y_move() * self.speeds.y_speed * (duration_s as CoordValue);
+            y += input.y_move() * y_speed * (duration_s as CoordValue);
             y = y.min(MAX_Y_VALUE - player_virtual_hitbox.bottom)
                 .max(MIN_Y_VALUE + player_virtual_hitbox.top);
         }
-        self.player_scene_object.pos = FPoint::new(x, y);
+        player.scene_object.pos = FPoint::new(x, y);
         if x_move < -0.2 {
-            self.player_scene_object.object_type = ObjectType::Player(PlayerState::TiltedLeft);
+            player.scene_object.object_type = ObjectType::Player(PlayerState::TiltedLeft);
         } else if x_move > 0.2 {
-            self.player_scene_object.object_type = ObjectType::Player(PlayerState::TiltedRight);
+            player.scene_object.object_type = ObjectType::Player(PlayerState::TiltedRight);
         } else {
-            self.player_scene_object.object_type = ObjectType::Player(PlayerState::Normal);
+            player.scene_object.object_type = ObjectType::Player(PlayerState::Normal);
         }
 
         let total_tilt = -input.left_tilt() * 90.0;
         let scale = 1.0 + 2.0 * input.right_tilt();
-        self.player_scene_object.set_sprite_scale(&self.sprite_data_cache, (scale, scale));
-        if (total_tilt - self.player_scene_object.sprite_angle().as_deg()).abs() > 1.0 {
-            self.player_scene_object
+        player.scene_object.set_sprite_scale(&self.sprite_data_cache, (scale, scale));
+        if (total_tilt - player.scene_object.sprite_angle().as_deg()).abs() > 1.0 {
+            player.scene_object
                 .set_sprite_angle(&self.sprite_data_cache, Angle::from_deg(total_tilt));
         }
+
+        player.hitbox_scene_object.pos = player.scene_object.pos;
+        if player.focused {
+            player.hitbox_scene_object
+                .set_sprite_scale(&self.sprite_data_cache, (scale, scale));
+            player.hitbox_scene_object
+                .set_sprite_angle(&self.sprite_data_cache, player.scene_object.sprite_angle());
+        }
+    }
+
+    fn drop_bomb(&mut self, player: usize) {
+        {
+            let player = &mut self.players[player];
+            player.bombs -= 1;
+            player.invulnerability_timeout = player
+                .invulnerability_timeout
+                .max(self.speeds.bomb_duration);
+            self.bomb_pos = player.scene_object.pos;
+        }
+        self.bomb_timeout = self.speeds.bomb_duration;
+        let converts = self.bomb_converts_bullets;
+        self.cancel_enemy_bullets(converts);
+        for enemy in &mut self.enemies {
+            let distance = enemy.pos - self.bomb_pos;
+            if distance.x().hypot(distance.y()) < self.speeds.bomb_radius {
+                enemy.health = enemy.health.saturating_sub(BOMB_DAMAGE);
+                enemy.owner = player;
+            }
+        }
+    }
+
+    fn cancel_enemy_bullets(&mut self, convert: bool) {
+        if convert {
+            for bullet in &self.enemy_bullets {
+                self.items
+                    .push(Scene::new_item(&self.sprite_data_cache,
+                                          &self.speeds,
+                                          &mut self.rng,
+                                          ObjectType::ScoreItem(0),
+                                          bullet.pos));
+            }
+        }
+        self.enemy_bullets.clear();
+    }
+
+    fn update_bomb(&mut self, duration_s: f32) {
+        self.bomb_timeout = (self.bomb_timeout - duration_s).max(0.0);
     }
 
     fn move_background(&mut self, duration_s: f32) {
@@ -338,34 +1459,65 @@ impl<'a> Scene<'a> {
         self.bullets_frame += self.speeds.bullet_blicking_speed * (duration_s as f32);
         let iter = (&mut self.player_bullets).iter_mut();
         let iter = iter.chain((&mut self.enemy_bullets).iter_mut());
+        let iter = iter.chain((&mut self.items).iter_mut());
         for bullet in iter {
             bullet.object_type = match bullet.object_type {
                 ObjectType::PlayerBullet(_) => ObjectType::PlayerBullet(self.bullets_frame as u32),
                 ObjectType::EnemyBullet(_) => ObjectType::EnemyBullet(self.bullets_frame as u32),
+                ObjectType::ScoreItem(_) => ObjectType::ScoreItem(self.bullets_frame as u32),
+                ObjectType::PowerItem(_) => ObjectType::PowerItem(self.bullets_frame as u32),
                 object_type => object_type,
             };
         }
     }
 
     fn move_bullets(&mut self, duration_s: f32) {
-        Scene::move_objects(&mut self.player_bullets,
-                            self.speeds.bullet_speed,
-                            duration_s);
-        Scene::move_objects(&mut self.enemy_bullets,
-                            self.speeds.bullet_speed,
-                            duration_s);
+        Scene::move_objects(&mut self.player_bullets, duration_s, true);
+        Scene::move_objects(&mut self.enemy_bullets, duration_s, true);
+    }
+
+    fn move_enemies(&mut self, duration_s: f32) {
+        for enemy in &mut self.enemies {
+            let segment = match enemy.behavior {
+                Some(behavior) => self.stage.path(behavior.path).segment_at(enemy.lifetime),
+                None => None,
+            };
+            enemy.lifetime += duration_s;
+            if let Some(segment) = segment {
+                enemy.direction_angle = segment.direction_angle;
+                enemy.speed = segment.speed;
+            }
+        }
+        Scene::move_objects(&mut self.enemies, duration_s, true);
+    }
+
+    // Items are launched upwards, so they may leave through the top edge before falling back.
+    fn move_items(&mut self, duration_s: f32) {
+        Scene::move_objects(&mut self.items, duration_s, false);
     }
 
-    fn move_objects(objects: &mut Vec<SceneObject>, speed: f32, duration_s: f32) {
+    fn move_objects(objects: &mut Vec<SceneObject>, duration_s: f32, cull_above: bool) {
         for object in objects.iter_mut() {
-            let distance = speed * duration_s;
+            object.speed += object.acceleration * duration_s;
+            if let Some(max_speed) = object.max_speed {
+                object.speed = object.speed.min(max_speed);
+            }
+            if let Some(min_speed) = object.min_speed {
+                object.speed = object.speed.max(min_speed);
+            }
+            if object.angular_velocity != 0.0 {
+                object.direction_angle = object
+                    .direction_angle
+                    .add_deg(object.angular_velocity * duration_s);
+            }
+            let distance = object.speed * duration_s;
             let direction_angle = object.direction_angle.as_rad();
             *object.pos.mut_x() += direction_angle.cos() * distance;
             *object.pos.mut_y() += direction_angle.sin() * distance;
         }
         objects.retain(|object| {
-                           object.pos.x() >= MIN_X_VALUE || object.pos.x() <= MAX_X_VALUE ||
-                           object.pos.y() >= MIN_Y_VALUE ||
+                           object.pos.x() >= MIN_X_VALUE && object.pos.x() <= MAX_X_VALUE &&
+                           (object.pos.y() >= MIN_Y_VALUE || !cull_above) &&
                            object.pos.y() <= MAX_Y_VALUE
                        });
     }
@@ -373,16 +1525,511 @@ impl<'a> Scene<'a> {
     fn detect_collisions(&mut self) {
         use std::iter;
 
-        self.collision_detector
-            .detect_collisions(&mut self.enemy_bullets,
-                               iter::once(&mut self.player_scene_object),
-                               |a, _| { a.to_delete = true; });
+        for player in &mut self.players {
+            if player.status == PlayerStatus::Alive && !player.is_invulnerable() {
+                self.collision_detector
+                    .detect_collisions(&mut self.enemy_bullets,
+                                       iter::once(&mut player.scene_object),
+                                       |a, b| {
+                                           a.to_delete = true;
+                                           b.to_delete = true;
+                                       });
+                self.collision_detector
+                    .detect_collisions(&mut self.enemies,
+                                       iter::once(&mut player.scene_object),
+                                       |_, b| { b.to_delete = true; });
+                let grazed = Cell::new(0);
+                self.collision_detector
+                    .detect_near_misses(&mut self.enemy_bullets,
+                                        iter::once(&mut player.scene_object),
+                                        self.graze_distance,
+                                        |a, _| if !a.grazed && !a.to_delete {
+                                            a.grazed = true;
+                                            grazed.set(grazed.get() + 1);
+                                        });
+                player.grazes += grazed.get();
+                player.score += grazed.get() as u64 * GRAZE_POINTS;
+            }
+            if player.status == PlayerStatus::Alive {
+                let collected = Cell::new(0);
+                let powered_up = Cell::new(0);
+                self.collision_detector
+                    .detect_collisions(&mut self.items,
+                                       iter::once(&mut player.scene_object),
+                                       |a, _| if !a.to_delete {
+                                           a.to_delete = true;
+                                           match a.object_type {
+                                               ObjectType::PowerItem(..) => {
+                                                   powered_up.set(powered_up.get() + 1)
+                                               }
+                                               _ => collected.set(collected.get() + 1),
+                                           }
+                                       });
+                player.score += collected.get() * SCORE_ITEM_POINTS * player.multiplier() as u64;
+                for _ in 0..powered_up.get() {
+                    if player.weapon_level < self.weapon.max_level() {
+                        player.weapon_level += 1;
+                    } else {
+                        player.score += POWER_ITEM_POINTS;
+                    }
+                }
+                self.items.retain(|item| !item.to_delete);
+            }
+        }
+        let cancelled = self.players
+            .iter()
+            .map(|_| Cell::new(0))
+            .collect::<Vec<_>>();
         self.collision_detector
             .detect_collisions(&mut self.enemy_bullets, &mut self.player_bullets, |a, b| {
-                a.to_delete = true;
+                if !a.to_delete {
+                    a.to_delete = true;
+                    if let Some(cancelled) = cancelled.get(b.owner) {
+                        cancelled.set(cancelled.get() + 1);
+                    }
+                }
                 b.to_delete = true;
             });
+        for (player, cancelled) in self.players.iter_mut().zip(cancelled) {
+            player.score += cancelled.get() * ENEMY_BULLET_POINTS;
+        }
+        self.collision_detector
+            .detect_collisions(&mut self.enemies, &mut self.player_bullets, |a, b| {
+                if !b.to_delete {
+                    b.to_delete = true;
+                    a.health = a.health.saturating_sub(b.damage);
+                    a.owner = b.owner;
+                }
+            });
         self.enemy_bullets.retain(|bullet| !bullet.to_delete);
         self.player_bullets.retain(|bullet| !bullet.to_delete);
     }
+
+    fn update_boss(&mut self, duration_s: f32) {
+        let mut state = match self.boss {
+            Some(state) => state,
+            None => return,
+        };
+        let boss = match self.enemies
+                  .iter()
+                  .position(|enemy| enemy.object_type == ObjectType::Boss) {
+            Some(boss) => boss,
+            None => {
+                self.boss = None;
+                return;
+            }
+        };
+        state.phase_time += duration_s;
+        let phases_count = self.stage.boss(state.boss).phases.len();
+        let phase: BossPhase = self.stage.boss(state.boss).phases[state.phase];
+        let timed_out = phase.duration.map_or(false, |duration| state.phase_time >= duration);
+        if self.enemies[boss].health != 0 && !timed_out {
+            self.boss = Some(state);
+            return;
+        }
+
+        self.cancel_enemy_bullets(true);
+        if state.phase + 1 < phases_count {
+            state.phase += 1;
+            state.phase_time = 0.0;
+            let next_phase = self.stage.boss(state.boss).phases[state.phase];
+            let enemy = &mut self.enemies[boss];
+            enemy.health = next_phase.health;
+            Scene::set_behavior(enemy, &self.bulletml_library, &self.stage, next_phase.behavior);
+            self.boss = Some(state);
+        } else {
+            self.enemies[boss].health = 0;
+            self.boss = None;
+        }
+    }
+
+    fn destroy_players(&mut self) {
+        for i in 0..self.players.len() {
+            if !self.players[i].scene_object.to_delete {
+                continue;
+            }
+            let lives = {
+                let player = &mut self.players[i];
+                player.scene_object.to_delete = false;
+                self.explosions
+                    .push(SceneObject::new(&self.sprite_data_cache,
+                                           ObjectType::Explosion(0),
+                                           player.scene_object.pos,
+                                           Angle::from_deg(0.0),
+                                           (1.0, 1.0)));
+                player.lives = player.lives.saturating_sub(1);
+                player.bombs = STARTING_BOMBS;
+                player.weapon_level = player.weapon_level.saturating_sub(POWER_LOST_ON_DEATH);
+                player.chain = 0;
+                if player.lives == 0 {
+                    player.status = PlayerStatus::GameOver;
+                    player.continue_timeout = self.speeds.continue_duration;
+                } else {
+                    player.status = PlayerStatus::Dead;
+                    player.respawn_timeout = self.speeds.respawn_duration;
+                }
+                player.lives
+            };
+            if self.lives_mode == LivesMode::Shared {
+                // An empty shared pool ends the game for the whole team at once.
+                for player in &mut self.players {
+                    player.lives = lives;
+                    if lives > 0 || player.status == PlayerStatus::GameOver {
+                        continue;
+                    }
+                    if player.status == PlayerStatus::Alive {
+                        self.explosions
+                            .push(SceneObject::new(&self.sprite_data_cache,
+                                                   ObjectType::Explosion(0),
+                                                   player.scene_object.pos,
+                                                   Angle::from_deg(0.0),
+                                                   (1.0, 1.0)));
+                    }
+                    player.status = PlayerStatus::GameOver;
+                    player.continue_timeout = self.speeds.continue_duration;
+                }
+            }
+        }
+    }
+
+    fn destroy_enemies(&mut self) {
+        for enemy in self.enemies.iter_mut().filter(|enemy| enemy.health == 0) {
+            enemy.to_delete = true;
+            self.explosions
+                .push(SceneObject::new(&self.sprite_data_cache,
+                                       ObjectType::Explosion(0),
+                                       enemy.pos,
+                                       Angle::from_deg(0.0),
+                                       (1.0, 1.0)));
+            let enemy_kind = match enemy.behavior {
+                Some(behavior) => self.stage.enemy_kind(behavior.enemy_kind),
+                None => continue,
+            };
+            if let Some(player) = self.players.get_mut(enemy.owner) {
+                player.chain += 1;
+                player.chain_timeout = self.speeds.chain_duration;
+                player.score += enemy_kind.points as u64 * player.multiplier() as u64;
+            }
+            for _ in 0..enemy_kind.drops {
+                self.items
+                    .push(Scene::new_item(&self.sprite_data_cache,
+                                          &self.speeds,
+                                          &mut self.rng,
+                                          ObjectType::ScoreItem(0),
+                                          enemy.pos));
+            }
+            for _ in 0..enemy_kind.power_drops {
+                self.items
+                    .push(Scene::new_item(&self.sprite_data_cache,
+                                          &self.speeds,
+                                          &mut self.rng,
+                                          ObjectType::PowerItem(0),
+                                          enemy.pos));
+            }
+        }
+        self.enemies.retain(|enemy| !enemy.to_delete);
+    }
+
+    fn new_item(sprite_data_cache: &SpriteDataCache,
+                speeds: &SpeedValues,
+                rng: &mut Rng,
+                object_type: ObjectType,
+                pos: FPoint)
+                -> SceneObject {
+        let mut item = SceneObject::new(sprite_data_cache,
+                                        object_type,
+                                        pos,
+                                        Angle::from_deg((rng.next_f32() - 0.5) * 90.0),
+                                        (1.0, 1.0));
+        item.speed = speeds.item_speed;
+        item.acceleration = -speeds.item_gravity;
+        item.min_speed = Some(-speeds.item_falling_speed);
+        item
+    }
+
+    fn update_chain(&mut self, duration_s: f32) {
+        for player in &mut self.players {
+            if player.chain_timeout > 0.0 {
+                player.chain_timeout -= duration_s;
+                if player.chain_timeout <= 0.0 {
+                    player.chain = 0;
+                }
+            }
+        }
+    }
+
+    fn animate_explosions(&mut self, duration_s: f32) {
+        for explosion in &mut self.explosions {
+            explosion.lifetime += duration_s;
+            let frame = (explosion.lifetime * self.speeds.explosion_speed) as u32;
+            explosion.object_type = ObjectType::Explosion(frame);
+            explosion.to_delete = frame >= EXPLOSION_FRAMES_COUNT;
+        }
+        self.explosions.retain(|explosion| !explosion.to_delete);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::InputState;
+
+    fn tick_duration() -> Duration {
+        Duration::new(0, 1_000_000_000 / 60)
+    }
+
+    fn run(scene: &mut Scene, ticks: ::std::ops::Range<usize>) {
+        for tick in ticks {
+            let x_move = if tick % 120 < 60 { 0.5 } else { -0.5 };
+            let inputs = [InputState::new(x_move, 0.0, true, false, tick % 500 == 0),
+                          InputState::new(-x_move, 0.2, true, tick % 90 < 30, false)];
+            scene.tick(&inputs, tick_duration());
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = Scene::with_players(&sprites, 7, 2);
+        run(&mut scene, 0..600);
+        let snapshot = scene.snapshot();
+
+        let mut restored = Scene::with_players(&sprites, 8, 2);
+        restored.restore(&snapshot).expect("Can't restore snapshot");
+        assert_eq!(restored.checksum(), scene.checksum());
+        assert_eq!(restored.snapshot(), snapshot);
+
+        run(&mut scene, 600..1200);
+        run(&mut restored, 600..1200);
+        assert_eq!(restored.checksum(), scene.checksum());
+
+        let checksum = scene.checksum();
+        scene.restore(&snapshot).expect("Can't restore snapshot");
+        run(&mut scene, 600..1200);
+        assert_eq!(scene.checksum(), checksum);
+    }
+
+    #[test]
+    fn restore_rejects_invalid_snapshots() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = Scene::with_players(&sprites, 7, 2);
+        run(&mut scene, 0..300);
+        let snapshot = scene.snapshot();
+        let checksum = scene.checksum();
+
+        assert!(scene.restore(&snapshot[..snapshot.len() - 1]).is_err());
+        assert!(scene.restore(&snapshot[..SNAPSHOT_MAGIC.len() + 2]).is_err());
+
+        let mut trailing = snapshot.clone();
+        trailing.push(0);
+        assert!(scene.restore(&trailing).is_err());
+
+        let mut bad_magic = snapshot.clone();
+        bad_magic[0] ^= 0xFF;
+        assert!(scene.restore(&bad_magic).is_err());
+
+        let mut bad_version = snapshot.clone();
+        bad_version[SNAPSHOT_MAGIC.len()] = SNAPSHOT_VERSION as u8 + 1;
+        assert!(scene.restore(&bad_version).is_err());
+
+        let mut single = Scene::with_players(&sprites, 7, 1);
+        assert!(single.restore(&snapshot).is_err());
+
+        assert_eq!(scene.checksum(), checksum);
+    }
+
+    #[test]
+    fn players_without_input_keep_their_timers_running() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = Scene::with_players(&sprites, 7, 3);
+        scene.players[1].status = PlayerStatus::Dead;
+        scene.players[1].respawn_timeout = 0.5;
+        scene.players[2].status = PlayerStatus::GameOver;
+        scene.players[2].continue_timeout = 0.5;
+        for _ in 0..60 {
+            scene.tick(&[InputState::default()], tick_duration());
+        }
+        assert_eq!(scene.players[1].status, PlayerStatus::Alive);
+        assert_eq!(scene.players[2].continue_timeout(), None);
+    }
+
+    #[test]
+    fn bomb_press_during_another_bomb_is_kept() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = Scene::with_players(&sprites, 7, 2);
+        let none = InputState::default();
+        let bomb = InputState::new(0.0, 0.0, false, false, true);
+        scene.tick(&[bomb, none], tick_duration());
+        assert_eq!(scene.players[0].bombs(), STARTING_BOMBS - 1);
+        while scene.bomb_timeout > 0.0 {
+            scene.tick(&[bomb, bomb], tick_duration());
+            assert_eq!(scene.players[1].bombs(), STARTING_BOMBS);
+        }
+        scene.tick(&[bomb, bomb], tick_duration());
+        assert_eq!(scene.players[0].bombs(), STARTING_BOMBS - 1);
+        assert_eq!(scene.players[1].bombs(), STARTING_BOMBS - 1);
+        while scene.bomb_timeout > 0.0 {
+            scene.tick(&[bomb, bomb], tick_duration());
+        }
+        scene.tick(&[bomb, bomb], tick_duration());
+        assert_eq!(scene.bomb_timeout, 0.0);
+    }
+
+    fn add_enemy_bullet(scene: &mut Scene, pos: FPoint) {
+        let bullet = SceneObject::new(&scene.sprite_data_cache,
+                                      ObjectType::EnemyBullet(0),
+                                      pos,
+                                      Angle::from_deg(0.0),
+                                      (1.0, 1.0));
+        scene.enemy_bullets.push(bullet);
+    }
+
+    #[test]
+    fn grazes_each_bullet_once() {
+        let sprites = SpritesData::new((200, 200));
+        let none = [InputState::default()];
+        let (mut scene, pos) = (1..200)
+            .map(|step| {
+                let offset = step as f32 * 0.001;
+                let mut scene = Scene::with_players(&sprites, 7, 1);
+                scene.players[0].invulnerability_timeout = 0.0;
+                let pos = scene.players[0].scene_object.pos + FPoint::new(offset, 0.0);
+                add_enemy_bullet(&mut scene, pos);
+                scene.tick(&none, tick_duration());
+                (scene, pos)
+            })
+            .find(|&(ref scene, _)| scene.players[0].grazes() > 0)
+            .expect("Can't graze a bullet");
+        assert_eq!(scene.players[0].grazes(), 1);
+        assert_eq!(scene.players[0].status(), PlayerStatus::Alive);
+
+        for _ in 0..30 {
+            scene.tick(&none, tick_duration());
+        }
+        assert_eq!(scene.players[0].grazes(), 1);
+
+        add_enemy_bullet(&mut scene, pos);
+        scene.tick(&none, tick_duration());
+        assert_eq!(scene.players[0].grazes(), 2);
+        assert_eq!(scene.players[0].status(), PlayerStatus::Alive);
+    }
+
+    const BOSS_STAGE: &'static str = "PATH HOLD 180 0.0 100.0
+                                      PATTERN QUIET NONE 1.0 0 0
+                                      BOSS KEEPER 1.0 5000 3 1
+                                      PHASE KEEPER 10 2.0 HOLD QUIET
+                                      PHASE KEEPER 20 - HOLD QUIET
+                                      PHASE KEEPER 30 3.0 HOLD QUIET
+                                      BOSS_WAVE 0.1 KEEPER 0.5 0.3";
+
+    fn boss_scene(sprites: &SpritesData) -> Scene {
+        let mut scene = Scene::with_players(sprites, 7, 1);
+        scene.stage = Stage::from_descr(BOSS_STAGE, &scene.bulletml_library);
+        while scene.boss.is_none() {
+            scene.tick(&[InputState::default()], tick_duration());
+        }
+        for i in 0..4 {
+            add_enemy_bullet(&mut scene, FPoint::new(0.2 * i as f32, 0.1));
+        }
+        scene
+    }
+
+    fn boss_object_health(scene: &Scene) -> u32 {
+        scene
+            .enemies
+            .iter()
+            .find(|enemy| enemy.object_type == ObjectType::Boss)
+            .expect("Can't find boss")
+            .health
+    }
+
+    fn boss_phase(scene: &Scene) -> Option<usize> {
+        scene.boss.map(|state| state.phase)
+    }
+
+    #[test]
+    fn boss_phase_ends_when_health_runs_out() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = boss_scene(&sprites);
+        assert_eq!(boss_phase(&scene), Some(0));
+        assert_eq!(boss_object_health(&scene), 10);
+
+        scene.update_boss(1.0);
+        assert_eq!(boss_phase(&scene), Some(0));
+        assert_eq!(scene.enemy_bullets.len(), 4);
+
+        scene.enemies[0].health = 0;
+        let items = scene.items.len();
+        scene.update_boss(0.1);
+        assert_eq!(boss_phase(&scene), Some(1));
+        assert_eq!(boss_object_health(&scene), 20);
+        assert_eq!(scene.boss_health(), Some(1.0));
+        assert!(scene.enemy_bullets.is_empty());
+        assert_eq!(scene.items.len(), items + 4);
+    }
+
+    #[test]
+    fn boss_phase_ends_on_timeout() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = boss_scene(&sprites);
+        scene.update_boss(1.9);
+        assert_eq!(boss_phase(&scene), Some(0));
+        scene.update_boss(0.2);
+        assert_eq!(boss_phase(&scene), Some(1));
+        assert_eq!(boss_object_health(&scene), 20);
+        assert!(scene.enemy_bullets.is_empty());
+
+        scene.update_boss(100.0);
+        assert_eq!(boss_phase(&scene), Some(1));
+    }
+
+    #[test]
+    fn boss_is_destroyed_after_last_phase() {
+        let sprites = SpritesData::new((200, 200));
+        for &timed_out in &[false, true] {
+            let mut scene = boss_scene(&sprites);
+            scene.update_boss(2.0);
+            scene.enemies[0].health = 0;
+            scene.update_boss(0.1);
+            assert_eq!(boss_phase(&scene), Some(2));
+            assert_eq!(boss_object_health(&scene), 30);
+
+            if timed_out {
+                scene.update_boss(3.0);
+            } else {
+                scene.enemies[0].health = 0;
+                scene.update_boss(0.1);
+            }
+            assert_eq!(boss_phase(&scene), None);
+            let (items, explosions, score) =
+                (scene.items.len(), scene.explosions.len(), scene.score());
+            scene.destroy_enemies();
+            assert!(scene.enemies.is_empty());
+            assert_eq!(scene.boss_health(), None);
+            assert_eq!(scene.items.len(), items + 4);
+            assert_eq!(scene.explosions.len(), explosions + 1);
+            assert_eq!(scene.score(), score + 5000);
+        }
+    }
+
+    #[test]
+    fn items_dropped_at_the_top_edge_fall_back() {
+        let sprites = SpritesData::new((200, 200));
+        let mut scene = Scene::with_players(&sprites, 7, 1);
+        for _ in 0..20 {
+            let item = Scene::new_item(&scene.sprite_data_cache,
+                                       &scene.speeds,
+                                       &mut scene.rng,
+                                       ObjectType::ScoreItem(0),
+                                       FPoint::new(0.5, MIN_Y_VALUE));
+            scene.items.push(item);
+        }
+        scene.move_items(seconds(tick_duration()));
+        assert!(scene.items.iter().any(|item| item.pos.y() < MIN_Y_VALUE));
+        for _ in 0..90 {
+            scene.move_items(seconds(tick_duration()));
+        }
+        assert_eq!(scene.items.len(), 20);
+        assert!(scene.items.iter().all(|item| item.pos.y() > MIN_Y_VALUE));
+    }
 }
@@ -0,0 +1,8 @@
+#version 150 core
+out vec4 out_color;
+
+uniform float u_dim;
+
+void main() {
+    out_color = vec4(0.0, 0.0, 0.0, u_dim);
+}
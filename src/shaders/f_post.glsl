@@ -6,6 +6,8 @@ out vec4 out_color;
 uniform sampler2D t_post;
 uniform uint u_virtual_width;
 uniform uint u_virtual_height;
+uniform vec2 u_bomb_center;
+uniform float u_bomb_progress;
 
 void main() {
     out_color = texture(t_post, f_tex_coord);
@@ -13,4 +15,9 @@ void main() {
         || fract(f_tex_coord.y * float(u_virtual_height)) < 0.2) {
         out_color *= vec4(0.5, 0.5, 0.5, 1.0);
     }
+    if (u_bomb_progress > 0.0) {
+        float wave = abs(distance(f_tex_coord, u_bomb_center) - u_bomb_progress * 1.5);
+        float intensity = (1.0 - u_bomb_progress) * (max(0.0, 1.0 - wave * 20.0) + 0.3);
+        out_color.rgb += vec3(intensity);
+    }
 }
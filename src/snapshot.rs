@@ -0,0 +1,207 @@
+use util::{Angle, FPoint};
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Default::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        for i in 0..4 {
+            self.write_u8((value >> (i * 8)) as u8);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        for i in 0..8 {
+            self.write_u8((value >> (i * 8)) as u8);
+        }
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn write_option_f32(&mut self, value: Option<f32>) {
+        self.write_bool(value.is_some());
+        if let Some(value) = value {
+            self.write_f32(value);
+        }
+    }
+
+    pub fn write_point(&mut self, point: FPoint) {
+        self.write_f32(point.x());
+        self.write_f32(point.y());
+    }
+
+    pub fn write_angle(&mut self, angle: Angle) {
+        self.write_f32(angle.as_rad());
+    }
+}
+
+#[derive(Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader {
+            bytes: bytes,
+            pos: 0,
+        }
+    }
+
+    pub fn finish(&self) -> Result<(), String> {
+        if self.pos == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(format!("Unexpected {} bytes at the end of snapshot", self.bytes.len() - self.pos))
+        }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return Err("Unexpected end of snapshot".to_string());
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        self.read_bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("Can't parse bool value {}", value)),
+        }
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        let bytes = self.read_bytes(4)?;
+        Ok(bytes
+               .iter()
+               .enumerate()
+               .fold(0, |value, (i, &byte)| value | (byte as u32) << (i * 8)))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let bytes = self.read_bytes(8)?;
+        Ok(bytes
+               .iter()
+               .enumerate()
+               .fold(0, |value, (i, &byte)| value | (byte as u64) << (i * 8)))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, String> {
+        let value = self.read_u64()?;
+        if value > ::std::usize::MAX as u64 {
+            return Err(format!("Can't fit {} into usize", value));
+        }
+        Ok(value as usize)
+    }
+
+    // An index into something with `len` elements, `name` only goes into the error message.
+    pub fn read_index(&mut self, len: usize, name: &str) -> Result<usize, String> {
+        let index = self.read_usize()?;
+        if index >= len {
+            return Err(format!("Invalid {} {} in snapshot", name, index));
+        }
+        Ok(index)
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, String> {
+        self.read_u32().map(f32::from_bits)
+    }
+
+    pub fn read_option_f32(&mut self) -> Result<Option<f32>, String> {
+        if self.read_bool()? {
+            self.read_f32().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn read_point(&mut self) -> Result<FPoint, String> {
+        let x = self.read_f32()?;
+        let y = self.read_f32()?;
+        Ok(FPoint::new(x, y))
+    }
+
+    pub fn read_angle(&mut self) -> Result<Angle, String> {
+        self.read_f32().map(Angle::from_rad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let mut writer = Writer::new();
+        writer.write_bytes(b"MAGIC");
+        writer.write_bool(true);
+        writer.write_u32(0xDEAD_BEEF);
+        writer.write_u64(0x0123_4567_89AB_CDEF);
+        writer.write_usize(42);
+        writer.write_f32(-1.5);
+        writer.write_option_f32(None);
+        writer.write_option_f32(Some(0.25));
+        writer.write_point(FPoint::new(0.5, 2.0));
+        writer.write_angle(Angle::from_rad(1.0));
+        let bytes = writer.into_bytes();
+
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.read_bytes(5), Ok(&b"MAGIC"[..]));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u32(), Ok(0xDEAD_BEEF));
+        assert_eq!(reader.read_u64(), Ok(0x0123_4567_89AB_CDEF));
+        assert_eq!(reader.read_index(43, "value"), Ok(42));
+        assert_eq!(reader.read_f32(), Ok(-1.5));
+        assert_eq!(reader.read_option_f32(), Ok(None));
+        assert_eq!(reader.read_option_f32(), Ok(Some(0.25)));
+        assert_eq!(reader.read_point(), Ok(FPoint::new(0.5, 2.0)));
+        assert_eq!(reader.read_angle().map(|angle| angle.as_rad()), Ok(1.0));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(Reader::new(&[1, 2, 3]).read_u32().is_err());
+        assert!(Reader::new(&[2]).read_bool().is_err());
+        assert!(Reader::new(&[5, 0, 0, 0, 0, 0, 0, 0]).read_index(5, "value").is_err());
+        let mut reader = Reader::new(&[0, 0]);
+        assert_eq!(reader.read_u8(), Ok(0));
+        assert!(reader.finish().is_err());
+    }
+}
@@ -13,6 +13,14 @@ pub enum SpriteObject {
     Player,
     PlayerBullet,
     EnemyBullet,
+    Enemy,
+    Explosion,
+    ScoreItem,
+    PowerItem,
+    Hitbox,
+    Boss,
+    Font,
+    Icons,
 }
 
 #[derive(Debug)]
@@ -80,6 +88,14 @@ impl SpritesData {
             "PLAYER" => Some(SpriteObject::Player),
             "PLAYER_BULLET" => Some(SpriteObject::PlayerBullet),
             "ENEMY_BULLET" => Some(SpriteObject::EnemyBullet),
+            "ENEMY" => Some(SpriteObject::Enemy),
+            "EXPLOSION" => Some(SpriteObject::Explosion),
+            "SCORE_ITEM" => Some(SpriteObject::ScoreItem),
+            "POWER_ITEM" => Some(SpriteObject::PowerItem),
+            "HITBOX" => Some(SpriteObject::Hitbox),
+            "BOSS" => Some(SpriteObject::Boss),
+            "FONT" => Some(SpriteObject::Font),
+            "ICONS" => Some(SpriteObject::Icons),
             _ => None,
         }
     }
@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use util::{parse_word, Angle, FPoint};
+use bulletml::Library;
+
+const STAGE_DESCR: &'static str = include_str!("../data/stage.txt");
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnemyKind {
+    pub health: u32,
+    pub scale: f32,
+    pub points: u32,
+    pub drops: u32,
+    pub power_drops: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PathSegment {
+    pub direction_angle: Angle,
+    pub speed: f32,
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+    looped: bool,
+}
+
+impl Path {
+    pub fn segment_at(&self, time: f32) -> Option<&PathSegment> {
+        let mut time = time;
+        if self.looped {
+            let duration = self.segments.iter().fold(0.0, |sum, segment| sum + segment.duration);
+            if duration > 0.0 {
+                time %= duration;
+            }
+        }
+        for segment in &self.segments {
+            if time < segment.duration {
+                return Some(segment);
+            }
+            time -= segment.duration;
+        }
+        self.segments.last()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternKind {
+    None,
+    Aimed,
+    Fixed,
+    Ring,
+    BulletML(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub interval: f32,
+    pub count: u32,
+    pub spread: f32,
+    pub speed: Option<f32>,
+    pub acceleration: f32,
+    pub angular_velocity: f32,
+    pub min_speed: Option<f32>,
+    pub max_speed: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Behavior {
+    pub enemy_kind: usize,
+    pub path: usize,
+    pub pattern: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Spawn {
+    pub at: f32,
+    pub pos: FPoint,
+    pub behavior: Behavior,
+    pub boss: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BossPhase {
+    pub health: u32,
+    pub duration: Option<f32>,
+    pub behavior: Behavior,
+}
+
+#[derive(Debug, Clone)]
+pub struct Boss {
+    pub enemy_kind: usize,
+    pub phases: Vec<BossPhase>,
+}
+
+#[derive(Debug)]
+pub struct Stage {
+    enemy_kinds: Vec<EnemyKind>,
+    paths: Vec<Path>,
+    patterns: Vec<Pattern>,
+    bosses: Vec<Boss>,
+    timed_spawns: Vec<Spawn>,
+    scrolled_spawns: Vec<Spawn>,
+    length: Option<f32>,
+}
+
+impl Stage {
+    pub fn new(bulletml_library: &Library) -> Stage {
+        Stage::from_descr(STAGE_DESCR, bulletml_library)
+    }
+
+    pub fn from_descr(descr: &str, bulletml_library: &Library) -> Stage {
+        let mut stage = Stage {
+            enemy_kinds: vec![],
+            paths: vec![],
+            patterns: vec![],
+            bosses: vec![],
+            timed_spawns: vec![],
+            scrolled_spawns: vec![],
+            length: None,
+        };
+        let mut enemy_kinds = HashMap::new();
+        let mut paths = HashMap::new();
+        let mut patterns = HashMap::new();
+        let mut bosses = HashMap::new();
+
+        for line in descr.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words[0] {
+                "ENEMY" => {
+                    if words.len() != 7 {
+                        panic!("Can't parse enemy description");
+                    }
+                    enemy_kinds.insert(words[1], stage.enemy_kinds.len());
+                    stage
+                        .enemy_kinds
+                        .push(EnemyKind {
+                                  health: parse_word(words[2], "stage"),
+                                  scale: parse_word(words[3], "stage"),
+                                  points: parse_word(words[4], "stage"),
+                                  drops: parse_word(words[5], "stage"),
+                                  power_drops: parse_word(words[6], "stage"),
+                              });
+                }
+                "PATH" => {
+                    let looped = words.get(2) == Some(&"LOOP");
+                    let first = if looped { 3 } else { 2 };
+                    if words.len() < first + 3 || (words.len() - first) % 3 != 0 {
+                        panic!("Can't parse path description");
+                    }
+                    let segments = words[first..]
+                        .chunks(3)
+                        .map(|segment| {
+                            PathSegment {
+                                direction_angle:
+                                    Angle::from_deg(parse_word::<f32>(segment[0], "stage") - 90.0),
+                                speed: parse_word(segment[1], "stage"),
+                                duration: parse_word(segment[2], "stage"),
+                            }
+                        })
+                        .collect();
+                    paths.insert(words[1], stage.paths.len());
+                    stage
+                        .paths
+                        .push(Path {
+                                  segments: segments,
+                                  looped: looped,
+                              });
+                }
+                "PATTERN" if words.len() == 4 && words[2] == "BULLETML" => {
+                    let document = bulletml_library
+                        .document_index(words[3])
+                        .expect("Can't find pattern's BulletML document");
+                    patterns.insert(words[1], stage.patterns.len());
+                    stage
+                        .patterns
+                        .push(Pattern {
+                                  kind: PatternKind::BulletML(document),
+                                  interval: 0.0,
+                                  count: 0,
+                                  spread: 0.0,
+                                  speed: None,
+                                  acceleration: 0.0,
+                                  angular_velocity: 0.0,
+                                  min_speed: None,
+                                  max_speed: None,
+                              });
+                }
+                "PATTERN" => {
+                    if words.len() != 6 && words.len() != 9 && words.len() != 11 {
+                        panic!("Can't parse pattern description");
+                    }
+                    let kind = match words[2] {
+                        "NONE" => PatternKind::None,
+                        "AIMED" => PatternKind::Aimed,
+                        "FIXED" => PatternKind::Fixed,
+                        "RING" => PatternKind::Ring,
+                        _ => panic!("Can't parse pattern kind"),
+                    };
+                    patterns.insert(words[1], stage.patterns.len());
+                    stage
+                        .patterns
+                        .push(Pattern {
+                                  kind: kind,
+                                  interval: parse_word(words[3], "stage"),
+                                  count: parse_word(words[4], "stage"),
+                                  spread: parse_word(words[5], "stage"),
+                                  speed: words.get(6).map(|word| parse_word(word, "stage")),
+                                  acceleration: words
+                                      .get(7)
+                                      .map_or(0.0, |word| parse_word(word, "stage")),
+                                  angular_velocity: words
+                                      .get(8)
+                                      .map_or(0.0, |word| parse_word(word, "stage")),
+                                  min_speed: words.get(9).and_then(|word| parse_limit(word)),
+                                  max_speed: words.get(10).and_then(|word| parse_limit(word)),
+                              });
+                }
+                "WAVE" => {
+                    if words.len() != 12 {
+                        panic!("Can't parse wave description");
+                    }
+                    let at: f32 = parse_word(words[2], "stage");
+                    let enemy_kind = *enemy_kinds
+                                          .get(words[3])
+                                          .expect("Can't find wave's enemy");
+                    let pos = FPoint::new(parse_word(words[4], "stage"),
+                                          parse_word(words[5], "stage"));
+                    let behavior = Behavior {
+                        enemy_kind: enemy_kind,
+                        path: *paths.get(words[6]).expect("Can't find wave's path"),
+                        pattern: *patterns.get(words[7]).expect("Can't find wave's pattern"),
+                    };
+                    let count: u32 = parse_word(words[8], "stage");
+                    let interval: f32 = parse_word(words[9], "stage");
+                    let offset = FPoint::new(parse_word(words[10], "stage"),
+                                             parse_word(words[11], "stage"));
+                    let spawns = match words[1] {
+                        "TIME" => &mut stage.timed_spawns,
+                        "SCROLL" => &mut stage.scrolled_spawns,
+                        _ => panic!("Can't parse wave trigger"),
+                    };
+                    let mut pos = pos;
+                    for i in 0..count {
+                        spawns.push(Spawn {
+                                        at: at + interval * i as f32,
+                                        pos: pos,
+                                        behavior: behavior,
+                                        boss: None,
+                                    });
+                        pos += offset;
+                    }
+                }
+                "BOSS" => {
+                    if words.len() != 6 {
+                        panic!("Can't parse boss description");
+                    }
+                    bosses.insert(words[1], stage.bosses.len());
+                    stage
+                        .bosses
+                        .push(Boss {
+                                  enemy_kind: stage.enemy_kinds.len(),
+                                  phases: vec![],
+                              });
+                    stage
+                        .enemy_kinds
+                        .push(EnemyKind {
+                                  health: 0,
+                                  scale: parse_word(words[2], "stage"),
+                                  points: parse_word(words[3], "stage"),
+                                  drops: parse_word(words[4], "stage"),
+                                  power_drops: parse_word(words[5], "stage"),
+                              });
+                }
+                "PHASE" => {
+                    if words.len() != 6 {
+                        panic!("Can't parse boss phase description");
+                    }
+                    let boss = *bosses.get(words[1]).expect("Can't find phase's boss");
+                    let behavior = Behavior {
+                        enemy_kind: stage.bosses[boss].enemy_kind,
+                        path: *paths.get(words[4]).expect("Can't find phase's path"),
+                        pattern: *patterns.get(words[5]).expect("Can't find phase's pattern"),
+                    };
+                    stage.bosses[boss]
+                        .phases
+                        .push(BossPhase {
+                                  health: parse_word(words[2], "stage"),
+                                  duration: parse_limit(words[3]),
+                                  behavior: behavior,
+                              });
+                }
+                "BOSS_WAVE" => {
+                    if words.len() != 5 {
+                        panic!("Can't parse boss wave description");
+                    }
+                    let boss = *bosses.get(words[2]).expect("Can't find wave's boss");
+                    let behavior = stage.bosses[boss]
+                        .phases
+                        .first()
+                        .expect("Can't find boss's first phase")
+                        .behavior;
+                    stage
+                        .timed_spawns
+                        .push(Spawn {
+                                  at: parse_word(words[1], "stage"),
+                                  pos: FPoint::new(parse_word(words[3], "stage"),
+                                                   parse_word(words[4], "stage")),
+                                  behavior: behavior,
+                                  boss: Some(boss),
+                              });
+                }
+                "END" => {
+                    if words.len() != 2 {
+                        panic!("Can't parse stage end");
+                    }
+                    stage.length = Some(parse_word(words[1], "stage"));
+                }
+                _ => panic!("Can't parse stage description"),
+            }
+        }
+
+        stage
+            .timed_spawns
+            .sort_by(|a, b| a.at.partial_cmp(&b.at).expect("Can't sort spawns"));
+        stage
+            .scrolled_spawns
+            .sort_by(|a, b| a.at.partial_cmp(&b.at).expect("Can't sort spawns"));
+        stage
+    }
+
+    pub fn enemy_kind(&self, index: usize) -> &EnemyKind {
+        &self.enemy_kinds[index]
+    }
+
+    pub fn path(&self, index: usize) -> &Path {
+        &self.paths[index]
+    }
+
+    pub fn pattern(&self, index: usize) -> &Pattern {
+        &self.patterns[index]
+    }
+
+    pub fn boss(&self, index: usize) -> &Boss {
+        &self.bosses[index]
+    }
+
+    pub fn enemy_kinds(&self) -> &[EnemyKind] {
+        &self.enemy_kinds
+    }
+
+    pub fn paths(&self) -> &[Path] {
+        &self.paths
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    pub fn bosses(&self) -> &[Boss] {
+        &self.bosses
+    }
+
+    pub fn timed_spawns(&self) -> &[Spawn] {
+        &self.timed_spawns
+    }
+
+    pub fn scrolled_spawns(&self) -> &[Spawn] {
+        &self.scrolled_spawns
+    }
+
+    pub fn length(&self) -> Option<f32> {
+        self.length
+    }
+}
+
+fn parse_limit(word: &str) -> Option<f32> {
+    if word == "-" {
+        None
+    } else {
+        Some(parse_word(word, "stage"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCR: &'static str = "# comment
+                                 ENEMY SMALL 4 0.75 100 1 0
+                                 ENEMY LARGE 24 1.5 1000 5 1
+                                 PATH DOWN 180 0.1 100.0
+                                 PATH SWAY LOOP 90 0.1 1.0 270 0.2 2.0
+                                 PATTERN QUIET NONE 1.0 0 0
+                                 PATTERN SWIRL RING 0.8 10 9 0.3 0.1 40 - 0.6
+                                 PATTERN FAST AIMED 1.2 3 20 0.5 -0.2 0
+                                 PATTERN SPIRAL BULLETML spiral
+                                 WAVE TIME 2.0 SMALL 0.2 0.0 DOWN QUIET 3 0.5 0.1 0.05
+                                 WAVE TIME 1.0 LARGE 0.5 0.0 SWAY SWIRL 1 0.0 0.0 0.0
+                                 WAVE SCROLL 1.5 SMALL 0.1 0.0 DOWN FAST 2 0.25 0.2 0.0
+                                 BOSS KEEPER 2.0 20000 20 2
+                                 PHASE KEEPER 60 20.0 DOWN SWIRL
+                                 PHASE KEEPER 80 - SWAY SPIRAL
+                                 BOSS_WAVE 4.0 KEEPER 0.5 0.1
+                                 END 10.0";
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn parses_default_stage() {
+        let library = Library::new();
+        let stage = Stage::new(&library);
+        assert!(!stage.timed_spawns().is_empty());
+        assert!(stage.timed_spawns().iter().any(|spawn| spawn.boss.is_some()));
+        assert!(stage.length().is_some());
+    }
+
+    #[test]
+    fn expands_waves() {
+        let library = Library::new();
+        let stage = Stage::from_descr(DESCR, &library);
+        assert_eq!(stage.length(), Some(10.0));
+
+        let timed = stage.timed_spawns();
+        let times = timed.iter().map(|spawn| spawn.at).collect::<Vec<_>>();
+        assert_eq!(times, vec![1.0, 2.0, 2.5, 3.0, 4.0]);
+        for (i, spawn) in timed[1..4].iter().enumerate() {
+            assert_eq!(spawn.behavior.enemy_kind, 0);
+            assert_eq!(spawn.behavior.path, 0);
+            assert_eq!(spawn.behavior.pattern, 0);
+            assert_eq!(spawn.boss, None);
+            assert_close(spawn.pos.x(), 0.2 + 0.1 * i as f32);
+            assert_close(spawn.pos.y(), 0.05 * i as f32);
+        }
+        assert_eq!(timed[0].behavior.enemy_kind, 1);
+        assert_eq!(timed[0].behavior.path, 1);
+
+        let scrolled = stage.scrolled_spawns();
+        assert_eq!(scrolled.len(), 2);
+        assert_eq!(scrolled[1].at, 1.75);
+        assert_close(scrolled[1].pos.x(), 0.3);
+        assert_eq!(scrolled[1].behavior.pattern, 2);
+    }
+
+    #[test]
+    fn loops_paths() {
+        let library = Library::new();
+        let stage = Stage::from_descr(DESCR, &library);
+        let cases = [(0, 0.0, 0.1), (0, 150.0, 0.1), (1, 0.5, 0.1), (1, 1.5, 0.2), (1, 3.5, 0.1),
+                     (1, 5.0, 0.2), (1, 300.25, 0.1)];
+        for &(path, time, speed) in &cases {
+            let segment = stage.path(path).segment_at(time).expect("Can't find path segment");
+            assert_eq!(segment.speed, speed, "path {} at {}", path, time);
+        }
+        let segment = stage.path(1).segment_at(1.5).expect("Can't find path segment");
+        assert_close(segment.direction_angle.as_deg(), 180.0);
+    }
+
+    #[test]
+    fn parses_pattern_fields() {
+        let library = Library::new();
+        let stage = Stage::from_descr(DESCR, &library);
+
+        let quiet = stage.pattern(0);
+        assert_eq!(quiet.kind, PatternKind::None);
+        assert_eq!(quiet.speed, None);
+        assert_eq!(quiet.acceleration, 0.0);
+        assert_eq!(quiet.angular_velocity, 0.0);
+        assert_eq!((quiet.min_speed, quiet.max_speed), (None, None));
+
+        let swirl = stage.pattern(1);
+        assert_eq!(swirl.kind, PatternKind::Ring);
+        assert_eq!((swirl.interval, swirl.count, swirl.spread), (0.8, 10, 9.0));
+        assert_eq!(swirl.speed, Some(0.3));
+        assert_eq!((swirl.acceleration, swirl.angular_velocity), (0.1, 40.0));
+        assert_eq!((swirl.min_speed, swirl.max_speed), (None, Some(0.6)));
+
+        let fast = stage.pattern(2);
+        assert_eq!(fast.kind, PatternKind::Aimed);
+        assert_eq!(fast.speed, Some(0.5));
+        assert_eq!((fast.acceleration, fast.angular_velocity), (-0.2, 0.0));
+        assert_eq!((fast.min_speed, fast.max_speed), (None, None));
+
+        let spiral = stage.pattern(3);
+        assert_eq!(spiral.kind, PatternKind::BulletML(library.document_index("spiral").unwrap()));
+    }
+
+    #[test]
+    fn links_bosses() {
+        let library = Library::new();
+        let stage = Stage::from_descr(DESCR, &library);
+        assert_eq!(stage.bosses().len(), 1);
+        let boss = stage.boss(0);
+        let kind = stage.enemy_kind(boss.enemy_kind);
+        assert_eq!(boss.enemy_kind, 2);
+        assert_eq!((kind.scale, kind.points, kind.drops, kind.power_drops), (2.0, 20000, 20, 2));
+
+        assert_eq!(boss.phases.len(), 2);
+        assert_eq!(boss.phases[0].health, 60);
+        assert_eq!(boss.phases[0].duration, Some(20.0));
+        assert_eq!(boss.phases[0].behavior.path, 0);
+        assert_eq!(boss.phases[0].behavior.pattern, 1);
+        assert_eq!(boss.phases[1].duration, None);
+        assert_eq!(boss.phases[1].behavior.path, 1);
+        assert!(boss.phases.iter().all(|phase| phase.behavior.enemy_kind == boss.enemy_kind));
+
+        let spawn = stage.timed_spawns()[4];
+        assert_eq!(spawn.boss, Some(0));
+        assert_eq!(spawn.behavior.pattern, boss.phases[0].behavior.pattern);
+        assert_eq!(spawn.behavior.enemy_kind, boss.enemy_kind);
+        assert_close(spawn.pos.y(), 0.1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't find wave's path")]
+    fn rejects_unknown_names() {
+        Stage::from_descr("ENEMY SMALL 4 0.75 100 1 0
+                           PATTERN QUIET NONE 1.0 0 0
+                           WAVE TIME 2.0 SMALL 0.2 0.0 DOWN QUIET 3 0.5 0.1 0.05",
+                          &Library::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't parse pattern description")]
+    fn rejects_malformed_patterns() {
+        Stage::from_descr("PATTERN FAST AIMED 1.2 3 20 0.5", &Library::new());
+    }
+}
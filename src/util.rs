@@ -1,6 +1,8 @@
 use std::f32;
 use std::ops::{Mul, Add, Div, Sub, AddAssign};
 use std::convert::From;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Point<T> {
@@ -213,3 +215,44 @@ impl Angle {
         Angle::from_deg(self.as_deg() + deg)
     }
 }
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    pub fn from_state(state: u64) -> Option<Rng> {
+        if state == 0 { None } else { Some(Rng { state: state }) }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+pub fn parse_word<T>(word: &str, descr_name: &str) -> T
+    where T: FromStr
+{
+    T::from_str(word)
+        .unwrap_or_else(|_| panic!("Can't parse a number from {} description", descr_name))
+}
+
+pub fn seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
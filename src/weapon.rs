@@ -0,0 +1,101 @@
+use util::parse_word;
+
+const WEAPON_DESCR: &'static str = include_str!("../data/weapon.txt");
+
+#[derive(Debug, Clone, Copy)]
+pub struct Shot {
+    pub angle: f32,
+    pub scale: f32,
+    pub damage: u32,
+}
+
+#[derive(Debug)]
+pub struct Weapon {
+    levels: Vec<Vec<Shot>>,
+}
+
+impl Weapon {
+    pub fn new() -> Weapon {
+        Weapon::from_descr(WEAPON_DESCR)
+    }
+
+    pub fn from_descr(descr: &str) -> Weapon {
+        let mut weapon = Weapon { levels: vec![] };
+
+        for line in descr.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words[0] != "SHOT" || words.len() != 5 {
+                panic!("Can't parse weapon description");
+            }
+            let level: usize = parse_word(words[1], "weapon");
+            while weapon.levels.len() <= level {
+                weapon.levels.push(vec![]);
+            }
+            weapon.levels[level].push(Shot {
+                                          angle: parse_word(words[2], "weapon"),
+                                          scale: parse_word(words[3], "weapon"),
+                                          damage: parse_word(words[4], "weapon"),
+                                      });
+        }
+
+        if weapon.levels.is_empty() {
+            panic!("Can't find weapon levels");
+        }
+        if weapon.levels.iter().any(|shots| shots.is_empty()) {
+            panic!("Can't find shots for every weapon level");
+        }
+        weapon
+    }
+
+    pub fn shots(&self, level: u32) -> &[Shot] {
+        &self.levels[(level as usize).min(self.levels.len() - 1)]
+    }
+
+    pub fn max_level(&self) -> u32 {
+        self.levels.len() as u32 - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_levels() {
+        let weapon = Weapon::from_descr("# SHOT level angle scale damage
+                                         SHOT 1 10 1.0 1
+                                         SHOT 0 0 2.0 1
+
+                                         SHOT 1 -10 1.5 2");
+        assert_eq!(weapon.max_level(), 1);
+        let shots = weapon.shots(1);
+        assert_eq!(shots.len(), 2);
+        assert_eq!((shots[0].angle, shots[0].scale, shots[0].damage), (10.0, 1.0, 1));
+        assert_eq!((shots[1].angle, shots[1].scale, shots[1].damage), (-10.0, 1.5, 2));
+        assert_eq!(weapon.shots(0).len(), 1);
+        assert_eq!(weapon.shots(5).len(), 2);
+        assert!(Weapon::new().max_level() > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't find shots for every weapon level")]
+    fn rejects_level_gaps() {
+        Weapon::from_descr("SHOT 0 0 2.0 1\nSHOT 2 0 2.0 2");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't find weapon levels")]
+    fn rejects_empty_weapons() {
+        Weapon::from_descr("# no shots");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't parse weapon description")]
+    fn rejects_malformed_shots() {
+        Weapon::from_descr("SHOT 0 0 2.0");
+    }
+}